@@ -0,0 +1,70 @@
+// Far terrain tiles, lit like a StandardMaterial through their vertex colours and dithered in
+// over the band next to the loaded chunks, see far_terrain.rs
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+#import bevy_pbr::pbr_types
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::pbr_ambient
+#import bevy_pbr::shadows
+#import bevy_pbr::fog
+#import bevy_pbr::pbr_functions
+
+// Centre of the loaded chunks on x and z, and the distances from it at which the tiles start
+// and finish fading in
+@group(1) @binding(0)
+var<uniform> fade: vec4<f32>;
+
+// Threshold of the 4x4 Bayer matrix at the fragment, spreading the discarded fragments evenly
+fn dither_threshold(frag_coord: vec2<f32>) -> f32 {
+    let x = u32(frag_coord.x) % 4u;
+    let y = u32(frag_coord.y) % 4u;
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    return (bayer[y * 4u + x] + 0.5) / 16.0;
+}
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    #import bevy_pbr::mesh_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    // The loaded chunks cover a square, so the band follows its sides
+    let offset = abs(in.world_position.xz - fade.xy);
+    let distance = max(offset.x, offset.y);
+    let opacity = clamp((distance - fade.z) / max(fade.w - fade.z, 0.001), 0.0, 1.0);
+    if (opacity < dither_threshold(in.frag_coord.xy)) {
+        discard;
+    }
+
+    var pbr_input: PbrInput = pbr_input_new();
+#ifdef VERTEX_COLORS
+    pbr_input.material.base_color = in.color;
+#endif
+    pbr_input.material.perceptual_roughness = 1.0;
+    pbr_input.material.metallic = 0.0;
+    pbr_input.material.reflectance = 0.125;
+    pbr_input.frag_coord = in.frag_coord;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = prepare_world_normal(in.world_normal, false, in.is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = normalize(pbr_input.world_normal);
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+
+    // Left out of the distance fog, which only has to hide the loading chunks in front of it
+    var output_color = pbr(pbr_input);
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color);
+#endif
+    return output_color;
+}
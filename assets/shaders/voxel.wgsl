@@ -0,0 +1,308 @@
+// Chunk meshes with packed vertices, see voxel_material.rs for the layout, or with VOXEL_SMOOTH
+// smooth meshes textured by triplanar projection, see build_smooth_mesh in chunk_mesh_builder.rs
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+#import bevy_pbr::pbr_types
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::pbr_ambient
+#import bevy_pbr::shadows
+#import bevy_pbr::fog
+#import bevy_pbr::pbr_functions
+
+#import bevy_pbr::mesh_functions
+
+@group(1) @binding(0)
+var spritesheet: texture_2d<f32>;
+@group(1) @binding(1)
+var spritesheet_sampler: sampler;
+// Width and height of the spritesheet, and size and padding of its sprites
+@group(1) @binding(2)
+var<uniform> sprite_layout: vec4<f32>;
+@group(1) @binding(3)
+var emissive_map: texture_2d<f32>;
+@group(1) @binding(4)
+var emissive_map_sampler: sampler;
+// Perceptual roughness and metallic, scaling those of the surface map, reflectance, and the
+// alpha below which fragments are discarded
+@group(1) @binding(5)
+var<uniform> surface: vec4<f32>;
+@group(1) @binding(6)
+var normal_map: texture_2d<f32>;
+@group(1) @binding(7)
+var normal_map_sampler: sampler;
+// Occlusion, roughness and metallic in red, green and blue
+@group(1) @binding(8)
+var surface_map: texture_2d<f32>;
+@group(1) @binding(9)
+var surface_map_sampler: sampler;
+
+#ifdef VOXEL_SMOOTH
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) sprites: vec2<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
+    // The sprites of one block for the whole triangle, rather than blends of unrelated ones
+    @location(3) @interpolate(flat) sprites: vec2<u32>,
+};
+#else
+struct Vertex {
+    @location(0) packed: vec2<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec3<f32>,
+    @location(4) world_tangent: vec4<f32>,
+};
+#endif
+
+// In the order of Side in face.rs
+fn side_normal(side: u32) -> vec3<f32> {
+    switch side {
+        case 0u: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 1u: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 2u: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 3u: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 4u: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 0.0, -1.0); }
+    }
+}
+
+// Direction the sprite's U runs along the side turned by rotation quarter turns, with the
+// handedness of its V in w, like Face::tangent in face.rs
+fn side_tangent(side: u32, rotation: u32) -> vec4<f32> {
+    // Where U and V run along the side when the sprite isn't turned
+    var tangent: vec3<f32>;
+    var bitangent: vec3<f32>;
+    switch side {
+        case 0u: { tangent = vec3<f32>(0.0, 0.0, -1.0); bitangent = vec3<f32>(0.0, -1.0, 0.0); }
+        case 1u: { tangent = vec3<f32>(0.0, 0.0, 1.0); bitangent = vec3<f32>(0.0, -1.0, 0.0); }
+        case 2u: { tangent = vec3<f32>(1.0, 0.0, 0.0); bitangent = vec3<f32>(0.0, 0.0, 1.0); }
+        case 3u: { tangent = vec3<f32>(-1.0, 0.0, 0.0); bitangent = vec3<f32>(0.0, 0.0, 1.0); }
+        case 4u: { tangent = vec3<f32>(1.0, 0.0, 0.0); bitangent = vec3<f32>(0.0, -1.0, 0.0); }
+        default: { tangent = vec3<f32>(-1.0, 0.0, 0.0); bitangent = vec3<f32>(0.0, -1.0, 0.0); }
+    }
+    let handedness = sign(dot(cross(side_normal(side), tangent), bitangent));
+    // Each quarter turn moves U to where V ran and V to where U ran back from
+    for (var turn = 0u; turn < rotation; turn++) {
+        let turned = bitangent;
+        bitangent = -tangent;
+        tangent = turned;
+    }
+    return vec4<f32>(tangent, handedness);
+}
+
+// The corner of the sprite, like get_uv_for_index in voxel_textures.rs
+fn sprite_uv(sprite: vec2<f32>, corner: u32) -> vec2<f32> {
+    let texture_size = sprite_layout.xy;
+    let sprite_size = sprite_layout.z;
+    let offset = sprite_layout.w;
+    let low = (sprite * sprite_size + (sprite * 2.0 + 1.0) * offset) / texture_size;
+    let high = ((sprite + 1.0) * sprite_size + ((sprite + 1.0) * 2.0 - 1.0) * offset) / texture_size;
+    switch corner {
+        case 0u: { return vec2<f32>(high.x, low.y); }
+        case 1u: { return vec2<f32>(low.x, low.y); }
+        case 2u: { return vec2<f32>(low.x, high.y); }
+        default: { return vec2<f32>(high.x, high.y); }
+    }
+}
+
+#ifdef VOXEL_SMOOTH
+// Sprite of the top, the bottom or the sides of the block, like pack_smooth_sprites in
+// voxel_material.rs
+fn smooth_sprite(sprites: vec2<u32>, side: u32) -> vec2<f32> {
+    var sprite: u32;
+    switch side {
+        case 2u: { sprite = sprites.x & 65535u; }
+        case 3u: { sprite = sprites.x >> 16u; }
+        default: { sprite = sprites.y; }
+    }
+    return vec2<f32>(f32(sprite & 255u), f32((sprite >> 8u) & 255u));
+}
+
+// Where the position lands in the sprite when projected onto the side, with the sprite laid
+// out over every voxel like on the faces of blocky meshes
+fn projected_uv(position: vec3<f32>, side: u32, sprite: vec2<f32>) -> vec2<f32> {
+    let tangent = side_tangent(side, 0u);
+    let bitangent = tangent.w * cross(side_normal(side), tangent.xyz);
+    let local = fract(vec2<f32>(dot(position, tangent.xyz), dot(position, bitangent)) + 0.5);
+    return mix(sprite_uv(sprite, 1u), sprite_uv(sprite, 3u), local);
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_normal_local_to_world(vertex.normal);
+    out.color = vertex.color.rgb;
+    out.sprites = vertex.sprites;
+    return out;
+}
+#else
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let packed = vertex.packed.x;
+    let position = vec3<f32>(
+        f32(packed & 31u),
+        f32((packed >> 5u) & 31u),
+        f32((packed >> 10u) & 31u),
+    ) - 0.5;
+    let side = (packed >> 15u) & 7u;
+    let corner = (packed >> 18u) & 3u;
+    // Darkened down to 0.4 when boxed in, like `occlusion_brightness`
+    let occlusion = mix(0.4, 1.0, f32((packed >> 20u) & 3u) / 3.0);
+    let rotation = (packed >> 22u) & 3u;
+    let sprite = vec2<f32>(f32(vertex.packed.y & 255u), f32((vertex.packed.y >> 8u) & 255u));
+    let tint = vertex.packed.y >> 16u;
+    let tint_color = vec3<f32>(
+        f32((tint >> 11u) & 31u) / 31.0,
+        f32((tint >> 5u) & 63u) / 63.0,
+        f32(tint & 31u) / 31.0,
+    );
+
+    var out: VertexOutput;
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(position, 1.0));
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_normal_local_to_world(side_normal(side));
+    out.world_tangent = mesh_tangent_local_to_world(mesh.model, side_tangent(side, rotation));
+    out.uv = sprite_uv(sprite, corner);
+    out.color = tint_color * occlusion;
+    return out;
+}
+#endif
+
+#ifdef VOXEL_SMOOTH
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
+    @location(3) @interpolate(flat) sprites: vec2<u32>,
+};
+
+// The projections of the texture onto the three axes, blended by how much the surface faces
+// each of them
+fn triplanar(
+    texture: texture_2d<f32>,
+    texture_sampler: sampler,
+    uvs: array<vec2<f32>, 3>,
+    weights: vec3<f32>,
+) -> vec4<f32> {
+    return textureSample(texture, texture_sampler, uvs[0]) * weights.x
+        + textureSample(texture, texture_sampler, uvs[1]) * weights.y
+        + textureSample(texture, texture_sampler, uvs[2]) * weights.z;
+}
+#else
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec3<f32>,
+    @location(4) world_tangent: vec4<f32>,
+};
+#endif
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+#ifdef VOXEL_SMOOTH
+    // Projected in world space, so the sprites line up across chunks. The render space only
+    // moves by whole chunks, which leaves the voxel grid where it was
+    let normal = normalize(in.world_normal);
+    let sides = vec3<u32>(
+        select(1u, 0u, normal.x > 0.0),
+        select(3u, 2u, normal.y > 0.0),
+        select(5u, 4u, normal.z > 0.0),
+    );
+    let position = in.world_position.xyz;
+    let uvs = array<vec2<f32>, 3>(
+        projected_uv(position, sides.x, smooth_sprite(in.sprites, sides.x)),
+        projected_uv(position, sides.y, smooth_sprite(in.sprites, sides.y)),
+        projected_uv(position, sides.z, smooth_sprite(in.sprites, sides.z)),
+    );
+    let sharpened = pow(abs(normal), vec3<f32>(4.0));
+    let weights = sharpened / (sharpened.x + sharpened.y + sharpened.z);
+    let base_color = triplanar(spritesheet, spritesheet_sampler, uvs, weights);
+    let emissive = triplanar(emissive_map, emissive_map_sampler, uvs, weights);
+    let surface_sample = triplanar(surface_map, surface_map_sampler, uvs, weights);
+    var mapped = array<vec3<f32>, 3>(
+        textureSample(normal_map, normal_map_sampler, uvs[0]).rgb * 2.0 - 1.0,
+        textureSample(normal_map, normal_map_sampler, uvs[1]).rgb * 2.0 - 1.0,
+        textureSample(normal_map, normal_map_sampler, uvs[2]).rgb * 2.0 - 1.0,
+    );
+#else
+    // Every texture is sampled before the alpha cutoff can discard the fragment
+    let base_color = textureSample(spritesheet, spritesheet_sampler, in.uv);
+    let emissive = textureSample(emissive_map, emissive_map_sampler, in.uv);
+    let surface_sample = textureSample(surface_map, surface_map_sampler, in.uv);
+    let mapped = textureSample(normal_map, normal_map_sampler, in.uv).rgb * 2.0 - 1.0;
+#endif
+    if (base_color.a < surface.w) {
+        discard;
+    }
+
+    // Lit like the StandardMaterial the other chunk meshes use
+    var pbr_input: PbrInput = pbr_input_new();
+    pbr_input.material.base_color = vec4<f32>(base_color.rgb * in.color, base_color.a);
+    pbr_input.material.emissive = emissive;
+    pbr_input.material.perceptual_roughness = surface.x * surface_sample.g;
+    pbr_input.material.metallic = surface.y * surface_sample.b;
+    pbr_input.material.reflectance = surface.z;
+#ifdef VOXEL_ALPHA_BLEND
+    pbr_input.material.flags = STANDARD_MATERIAL_FLAGS_ALPHA_MODE_BLEND;
+#endif
+    pbr_input.occlusion = surface_sample.r;
+    pbr_input.frag_coord = in.frag_coord;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = prepare_world_normal(in.world_normal, false, in.is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+#ifdef VOXEL_SMOOTH
+    // The smooth mesh has no tangents, so each projection's normal is bent in the frame of
+    // the side it's projected onto, and the bends are blended onto the surface normal
+    var bend = vec3<f32>(0.0);
+    for (var axis = 0u; axis < 3u; axis++) {
+        let side = sides[axis];
+        let side_normal = side_normal(side);
+        let tangent = side_tangent(side, 0u);
+        let bitangent = tangent.w * cross(side_normal, tangent.xyz);
+        let bent = mapped[axis].x * tangent.xyz + mapped[axis].y * bitangent
+            + mapped[axis].z * side_normal;
+        bend += (bent - side_normal) * weights[axis];
+    }
+    pbr_input.N = normalize(pbr_input.world_normal + bend);
+#else
+    // Bent by the normal map like a StandardMaterial's, with V along the bitangent
+    let normal = normalize(pbr_input.world_normal);
+    let tangent = normalize(in.world_tangent.xyz);
+    let bitangent = in.world_tangent.w * cross(normal, tangent);
+    pbr_input.N = normalize(mapped.x * tangent + mapped.y * bitangent + mapped.z * normal);
+#endif
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+
+    var output_color = pbr(pbr_input);
+    if (fog.mode != FOG_MODE_OFF) {
+        output_color = apply_fog(output_color, in.world_position.xyz, view.world_position.xyz);
+    }
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color);
+#endif
+    return output_color;
+}
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    chunk_manager::ChunkManager,
+    voxel::{BlockMaterial, VoxelType},
+    voxel_material::VoxelMaterial,
+};
+
+/// Spawns the entity drawing a submesh with a game material
+type SpawnSubmesh = dyn Fn(&mut Commands, Handle<Mesh>) -> Entity + Send + Sync;
+
+/// How the submeshes of a registered material are drawn
+#[derive(Clone)]
+pub enum MaterialDraw {
+    /// With the spritesheet, by a `StandardMaterial` for standard vertices and a
+    /// `VoxelMaterial` for packed and smooth ones
+    Spritesheet {
+        standard: Handle<StandardMaterial>,
+        packed: Handle<VoxelMaterial>,
+    },
+    /// By a material of the game's own, whatever the vertices. Its shader gets the attributes
+    /// of the configured `ChunkVertexFormat`, or of smooth meshes
+    Custom(Arc<SpawnSubmesh>),
+}
+
+#[derive(Clone)]
+pub struct RegisteredMaterial {
+    /// Whether what's behind the block shows through it, so the faces of the blocks next to
+    /// it are kept and it doesn't hide the chunks behind it
+    pub see_through: bool,
+    pub draw: MaterialDraw,
+}
+
+/// Materials block faces are drawn with, by `BlockMaterial` id, and the material of each block
+/// type. The `BlockMaterial::BUILT_IN` come first, game code registers its own after them and
+/// maps blocks to them. Changing them remeshes the loaded chunks
+#[derive(Resource, Clone)]
+pub struct BlockMaterials {
+    materials: Vec<RegisteredMaterial>,
+    /// Block types drawn with another material than `VoxelType::material`
+    blocks: HashMap<VoxelType, BlockMaterial>,
+}
+
+impl Default for BlockMaterials {
+    fn default() -> Self {
+        let materials = BlockMaterial::BUILT_IN
+            .into_iter()
+            .map(|material| RegisteredMaterial {
+                see_through: matches!(material, BlockMaterial::CUTOUT | BlockMaterial::TRANSLUCENT),
+                draw: MaterialDraw::Spritesheet {
+                    standard: Handle::default(),
+                    packed: Handle::default(),
+                },
+            })
+            .collect();
+        Self {
+            materials,
+            blocks: HashMap::new(),
+        }
+    }
+}
+
+impl BlockMaterials {
+    /// Register a material of the game's own, drawn as a `MaterialMeshBundle<M>`
+    pub fn register<M: Material>(
+        &mut self,
+        material: Handle<M>,
+        see_through: bool,
+    ) -> BlockMaterial {
+        let spawn = move |commands: &mut Commands, mesh: Handle<Mesh>| {
+            commands
+                .spawn(MaterialMeshBundle {
+                    mesh,
+                    material: material.clone(),
+                    ..default()
+                })
+                .id()
+        };
+        self.materials.push(RegisteredMaterial {
+            see_through,
+            draw: MaterialDraw::Custom(Arc::new(spawn)),
+        });
+        BlockMaterial(self.materials.len() as u16 - 1)
+    }
+
+    /// Point one of the `BlockMaterial::BUILT_IN` at the spritesheet materials it's drawn with
+    pub fn set_spritesheet_materials(
+        &mut self,
+        material: BlockMaterial,
+        standard: Handle<StandardMaterial>,
+        packed: Handle<VoxelMaterial>,
+    ) {
+        if let Some(registered) = self.materials.get_mut(material.index()) {
+            registered.draw = MaterialDraw::Spritesheet { standard, packed };
+        }
+    }
+
+    /// Draw the block type with a registered material
+    pub fn set_block_material(&mut self, voxel_type: VoxelType, material: BlockMaterial) {
+        self.blocks.insert(voxel_type, material);
+    }
+
+    /// Material the block type is drawn with
+    pub fn block_material(&self, voxel_type: VoxelType) -> BlockMaterial {
+        match self.blocks.get(&voxel_type) {
+            Some(material) if material.index() < self.materials.len() => *material,
+            _ => voxel_type.material(),
+        }
+    }
+
+    /// Whether what's behind the block type shows through it
+    pub fn is_see_through(&self, voxel_type: VoxelType) -> bool {
+        self.get(self.block_material(voxel_type)).see_through
+    }
+
+    /// Number of registered materials, the ids run up to it
+    pub fn count(&self) -> usize {
+        self.materials.len()
+    }
+
+    /// The registered materials, in order of their ids
+    pub fn ids(&self) -> impl Iterator<Item = BlockMaterial> {
+        (0..self.materials.len() as u16).map(BlockMaterial)
+    }
+
+    /// The registered material, or the opaque one for an id that was never registered
+    pub fn get(&self, material: BlockMaterial) -> &RegisteredMaterial {
+        self.materials
+            .get(material.index())
+            .unwrap_or(&self.materials[BlockMaterial::OPAQUE.index()])
+    }
+
+    /// The spritesheet materials, for switching them to another resource pack
+    pub fn spritesheet_materials(
+        &self,
+    ) -> impl Iterator<Item = (&Handle<StandardMaterial>, &Handle<VoxelMaterial>)> {
+        self.materials
+            .iter()
+            .filter_map(|registered| match &registered.draw {
+                MaterialDraw::Spritesheet { standard, packed } => Some((standard, packed)),
+                MaterialDraw::Custom(_) => None,
+            })
+    }
+
+    /// Spawn the entity drawing a submesh with the material. `voxel_vertices` is whether the
+    /// mesh has packed or smooth vertices, which the spritesheet draws with a `VoxelMaterial`
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        material: BlockMaterial,
+        mesh: Handle<Mesh>,
+        voxel_vertices: bool,
+    ) -> Entity {
+        match &self.get(material).draw {
+            MaterialDraw::Spritesheet { packed, .. } if voxel_vertices => commands
+                .spawn(MaterialMeshBundle {
+                    mesh,
+                    material: packed.clone(),
+                    ..default()
+                })
+                .id(),
+            MaterialDraw::Spritesheet { standard, .. } => commands
+                .spawn(MaterialMeshBundle {
+                    mesh,
+                    material: standard.clone(),
+                    ..default()
+                })
+                .id(),
+            MaterialDraw::Custom(spawn) => spawn(commands, mesh),
+        }
+    }
+}
+
+pub struct BlockMaterialsPlugin;
+
+impl Plugin for BlockMaterialsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockMaterials>()
+            .add_system(apply_block_materials);
+    }
+}
+
+pub fn apply_block_materials(
+    commands: Commands,
+    block_materials: Res<BlockMaterials>,
+    mut chunk_manager: ResMut<ChunkManager>,
+) {
+    if block_materials.is_changed() {
+        chunk_manager.set_block_materials(block_materials.clone(), commands);
+    }
+}
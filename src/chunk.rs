@@ -1,7 +1,11 @@
 use bevy::prelude::IVec3;
 use rand::prelude::*;
 
-use crate::{chunk_manager::ChunkManager, face::Side, voxel::VoxelType};
+use crate::{
+    block_materials::BlockMaterials, chunk_connectivity::ChunkConnectivity,
+    chunk_manager::ChunkManager, face::Side, voxel::VoxelType, world_coords::I64Vec3,
+    world_generator::WorldGenerator,
+};
 
 use super::voxel::Voxel;
 
@@ -16,6 +20,15 @@ lazy_static! {
 pub struct Chunk {
     pub voxels: [Voxel; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE],
     pub empty: bool,
+    /// Which of the chunk's sides can be seen from which others, kept up to date with the
+    /// voxels by `update_connectivity`
+    pub connectivity: ChunkConnectivity,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::new()
+    }
 }
 
 impl Chunk {
@@ -23,6 +36,7 @@ impl Chunk {
         Self {
             voxels: [Voxel::default(); CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE],
             empty: true,
+            connectivity: ChunkConnectivity::ALL,
         }
     }
 
@@ -54,16 +68,11 @@ impl Chunk {
                     if let Some(voxel) = self.voxels.get_mut(index) {
                         let (f_x, f_y, f_z) = (x as f32, y as f32, z as f32);
                         let f_radius = radius as f32;
-                        if f32::sqrt(
+                        voxel.active = f32::sqrt(
                             (f_x - f_radius) * (f_x - f_radius)
                                 + (f_y - f_radius) * (f_y - f_radius)
                                 + (f_z - f_radius) * (f_z - f_radius),
-                        ) <= f_radius
-                        {
-                            voxel.active = true;
-                        } else {
-                            voxel.active = false;
-                        }
+                        ) <= f_radius;
                     }
                 }
             }
@@ -71,29 +80,16 @@ impl Chunk {
         self.check_empty();
     }
 
-    pub fn setup_perlin(&mut self, chunk_pos: IVec3, seed: u32) {
-        use noise::{NoiseFn, Perlin};
-
-        let perlin = Perlin::new(seed);
-        for (index, voxel) in self.voxels.iter_mut().enumerate() {
-            let coord = Self::get_coordinate(index);
-            let (chunk_x, chunk_y, chunk_z) = (
-                chunk_pos.x as f64 * CHUNK_SIZE as f64,
-                chunk_pos.y as f64 * CHUNK_SIZE as f64,
-                chunk_pos.z as f64 * CHUNK_SIZE as f64,
-            );
-
-            let down_scale = 0.027f64;
-            let x = (chunk_x + coord.x as f64) * down_scale;
-            let y = (chunk_y + coord.y as f64) * down_scale;
-            let z = (chunk_z + coord.z as f64) * down_scale;
-            let density = perlin.get([x, y, z]);
-            if density > 0.3f64 {
-                voxel.active = true;
-                voxel.voxel_type = VoxelType::Grass;
-            } else {
-                voxel.active = false;
-                voxel.voxel_type = VoxelType::None;
+    pub fn setup_generated(&mut self, chunk_pos: I64Vec3, generator: &WorldGenerator) {
+        let chunk_origin = ChunkManager::chunk_to_world_coords(&chunk_pos, &IVec3::ZERO);
+        // Column by column, so what's the same for all of a column is only looked up once
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let column = generator.column(chunk_origin.x + x as i64, chunk_origin.z + z as i64);
+                for y in 0..CHUNK_SIZE {
+                    self.voxels[Chunk::index_from(x, y, z)] =
+                        column.voxel(chunk_origin.y + y as i64);
+                }
             }
         }
 
@@ -104,7 +100,7 @@ impl Chunk {
         (coordinate.z | (coordinate.y << *BIT_SIZE) | (coordinate.x << (*BIT_SIZE * 2))) as usize
     }
     pub fn index_from(x: usize, y: usize, z: usize) -> usize {
-        (z | (y << *BIT_SIZE) | (x << (*BIT_SIZE * 2))) as usize
+        z | (y << *BIT_SIZE) | (x << (*BIT_SIZE * 2))
     }
 
     pub fn get_coordinate(index: usize) -> IVec3 {
@@ -122,6 +118,12 @@ impl Chunk {
         self.voxels.get_mut(index)
     }
 
+    /// Work out which sides can be seen from which others again, with the materials the
+    /// voxels are drawn with
+    pub fn update_connectivity(&mut self, materials: &BlockMaterials) {
+        self.connectivity = ChunkConnectivity::new(self, materials);
+    }
+
     pub fn check_empty(&mut self) -> bool {
         for x in 0..CHUNK_SIZE {
             for y in 0..CHUNK_SIZE {
@@ -140,7 +142,7 @@ impl Chunk {
         true
     }
 
-    pub fn update_voxel_data(&mut self, chunk_manager: &ChunkManager, chunk_pos: &IVec3) {
+    pub fn update_voxel_data(&mut self, chunk_manager: &ChunkManager, chunk_pos: &I64Vec3) {
         for x in 0..CHUNK_SIZE {
             for y in 0..CHUNK_SIZE {
                 for z in 0..CHUNK_SIZE {
@@ -172,5 +174,6 @@ impl Chunk {
             }
         }
         self.check_empty();
+        self.update_connectivity(chunk_manager.block_materials());
     }
 }
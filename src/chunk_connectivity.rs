@@ -0,0 +1,128 @@
+use bevy::prelude::IVec3;
+
+use crate::{
+    block_materials::BlockMaterials,
+    chunk::{Chunk, CHUNK_SIZE},
+    chunk_mesh_builder::NEIGHBOUR_OFFSETS,
+    face::Side,
+    voxel::Voxel,
+};
+
+/// Which sides of a chunk can be seen from which others, through the voxels that can be
+/// seen through. Used to skip drawing chunks hidden behind others, like caves under hills
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkConnectivity {
+    /// Per side, in the order of `Side`, a bit for each side it connects to
+    sides: [u8; 6],
+}
+
+impl ChunkConnectivity {
+    /// Every side connects to every other, as in an empty chunk
+    pub const ALL: ChunkConnectivity = ChunkConnectivity {
+        sides: [0b111111; 6],
+    };
+
+    /// Flood fill the see-through voxels, connecting the sides each region touches
+    pub fn new(chunk: &Chunk, materials: &BlockMaterials) -> Self {
+        let is_see_through =
+            |voxel: &Voxel| !voxel.active || materials.is_see_through(voxel.voxel_type);
+        let mut connectivity = ChunkConnectivity { sides: [0; 6] };
+        let mut visited = vec![false; chunk.voxels.len()];
+        let mut stack = Vec::new();
+        for start in 0..chunk.voxels.len() {
+            if visited[start] || !is_see_through(&chunk.voxels[start]) {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start);
+            let mut touched = 0u8;
+            while let Some(index) = stack.pop() {
+                let pos = Chunk::get_coordinate(index);
+                for (side, offset) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+                    let neighbour_pos = pos + *offset;
+                    if !is_inside(neighbour_pos) {
+                        touched |= 1 << side;
+                        continue;
+                    }
+                    let neighbour = Chunk::get_index(&neighbour_pos);
+                    if !visited[neighbour] && is_see_through(&chunk.voxels[neighbour]) {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            for side in 0..6 {
+                if touched & (1 << side) != 0 {
+                    connectivity.sides[side] |= touched;
+                }
+            }
+        }
+        connectivity
+    }
+
+    /// Whether something seen through the `from` side can be seen going on out of the `to` side
+    pub fn connects(&self, from: Side, to: Side) -> bool {
+        self.sides[from as usize] & (1 << to as usize) != 0
+    }
+}
+
+fn is_inside(pos: IVec3) -> bool {
+    pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(CHUNK_SIZE as i32)).all()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const CENTER: i32 = CHUNK_SIZE as i32 / 2;
+
+    /// Solid all through, with a pocket of air in the middle that touches no side
+    pub(crate) fn sealed_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.voxels = [Voxel::new(true); CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        for offset in [IVec3::ZERO, IVec3::X, IVec3::Y, IVec3::Z] {
+            chunk.voxels[Chunk::get_index(&(IVec3::splat(CENTER) + offset))] = Voxel::new_empty();
+        }
+        chunk.check_empty();
+        chunk.update_connectivity(&BlockMaterials::default());
+        chunk
+    }
+
+    /// Solid, with a tunnel running in from the left side to the middle and turning up out of
+    /// the top
+    pub(crate) fn tunnel_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.voxels = [Voxel::new(true); CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        for x in 0..=CENTER {
+            chunk.voxels[Chunk::get_index(&IVec3::new(x, CENTER, CENTER))] = Voxel::new_empty();
+        }
+        for y in CENTER..CHUNK_SIZE as i32 {
+            chunk.voxels[Chunk::get_index(&IVec3::new(CENTER, y, CENTER))] = Voxel::new_empty();
+        }
+        chunk.check_empty();
+        chunk.update_connectivity(&BlockMaterials::default());
+        chunk
+    }
+
+    #[test]
+    fn sealed_chunk_connects_no_sides() {
+        let connectivity = sealed_chunk().connectivity;
+        for from in Side::ALL {
+            for to in Side::ALL {
+                assert!(!connectivity.connects(from, to));
+            }
+        }
+    }
+
+    #[test]
+    fn tunnel_connects_only_its_ends() {
+        let connectivity = tunnel_chunk().connectivity;
+        assert!(connectivity.connects(Side::Left, Side::Top));
+        assert!(connectivity.connects(Side::Top, Side::Left));
+        for side in [Side::Right, Side::Bottom, Side::Front, Side::Back] {
+            assert!(!connectivity.connects(Side::Left, side));
+            assert!(!connectivity.connects(Side::Top, side));
+            assert!(!connectivity.connects(side, side));
+        }
+    }
+}
@@ -1,17 +1,34 @@
 use std::collections::VecDeque;
 
+use crate::block_materials::BlockMaterials;
 use crate::chunk::*;
-use crate::face::Side;
-use crate::voxel::{Voxel, VoxelType};
+use crate::chunk_connectivity::ChunkConnectivity;
+use crate::chunk_mesh_builder::{
+    ChunkVertexFormat, MeshLod, MeshingMode, Submesh, MAX_LOD_LEVEL, NEIGHBOUR_OFFSETS,
+};
+use crate::chunk_transition::{ChunkTransition, SlidingIn};
+use crate::face::{Side, HALF_SIZE};
+use crate::padded_chunk::{neighbour_offset, MissingNeighbours, PaddedChunk};
+use crate::render_settings::RenderSettings;
+use crate::structures::{self, StructureAnchors, StructureTemplate};
+use crate::tint_overrides::TintOverrides;
+use crate::voxel::{BlockMaterial, Voxel, VoxelType};
+use crate::voxel_material::{unpack_position, ATTRIBUTE_PACKED_VOXEL, ATTRIBUTE_SMOOTH_SPRITES};
+use crate::voxel_textures::BlockTextures;
+use crate::world_coords::I64Vec3;
+use crate::world_gen_settings::WorldGenSettings;
+use crate::world_generator::WorldGenerator;
 use crate::{chunk::Chunk, chunk_mesh_builder};
 use bevy::asset::HandleId;
 use bevy::pbr::NotShadowCaster;
 use bevy::prelude::*;
 use bevy::prelude::{Commands, Transform};
-use bevy::render::primitives::Frustum;
-use bevy::utils::hashbrown::HashMap;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::render::primitives::{Aabb, Frustum};
+use bevy::utils::hashbrown::hash_map::Entry;
+use bevy::utils::hashbrown::{HashMap, HashSet};
 use bevy::utils::Uuid;
-use bevy_rapier3d::prelude::{Collider, ComputedColliderShape};
+use bevy_rapier3d::prelude::Collider;
 
 pub const MAX_CHUNKS: usize = 10000;
 pub const MAX_MESHES: usize = 10000;
@@ -28,6 +45,8 @@ pub const MAX_UNLOAD_MESHES_PER_FRAME: usize = 8;
 pub const MAX_MESHES_TO_RENDER_LIST: usize = 32;
 pub const MAX_RENDER_MESHES_PER_FRAME: usize = 4;
 pub const DEFAULT_RENDER_DISTANCE: i32 = 8;
+/// Distances, in chunks from the camera, where meshes drop to the next level of detail
+pub const DEFAULT_LOD_DISTANCES: [i32; MAX_LOD_LEVEL as usize] = [3, 5, 7];
 
 #[derive(Debug)]
 pub enum ChunkError {
@@ -35,25 +54,146 @@ pub enum ChunkError {
     NoVoxel,
 }
 
+/// The entity holding a chunk's transform and collider, with a child drawing each of its
+/// submeshes. All are kept while the chunk is rebuilt
+pub struct RenderedChunk {
+    pub entity: Entity,
+    /// Child entity and mesh asset of the submesh of each `BlockMaterial`
+    submeshes: HashMap<BlockMaterial, (Entity, Handle<Mesh>)>,
+}
+
+impl RenderedChunk {
+    pub fn spawn(commands: &mut Commands, transform: Transform) -> Self {
+        let entity = commands
+            .spawn(SpatialBundle::from_transform(transform))
+            .id();
+        Self {
+            entity,
+            submeshes: HashMap::new(),
+        }
+    }
+
+    /// Draw the submeshes, updating the mesh assets of the materials drawn before in place
+    /// and spawning children for the others. Returns the children spawned
+    pub fn update_submeshes(
+        &mut self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        block_materials: &BlockMaterials,
+        submeshes: &[Submesh],
+    ) -> Vec<Entity> {
+        let mut spawned = Vec::new();
+        for submesh in submeshes {
+            let mesh = &submesh.mesh;
+            let packed = mesh.attribute(ATTRIBUTE_PACKED_VOXEL).is_some();
+            let smooth = mesh.attribute(ATTRIBUTE_SMOOTH_SPRITES).is_some();
+            if let Some((entity, mesh_handle)) = self.submeshes.get(&submesh.material) {
+                if let Some(mesh_asset) = meshes.get_mut(mesh_handle) {
+                    *mesh_asset = mesh.clone();
+                    // Bevy only computes the bounds of new entities
+                    if !packed {
+                        if let Some(aabb) = mesh.compute_aabb() {
+                            commands.entity(*entity).insert(aabb);
+                        }
+                    }
+                    continue;
+                }
+                commands.entity(*entity).despawn_recursive();
+            }
+
+            let mesh_handle = meshes.add(mesh.clone());
+            let entity = block_materials.spawn(
+                commands,
+                submesh.material,
+                mesh_handle.clone(),
+                packed || smooth,
+            );
+            let mut submesh_commands = commands.entity(entity);
+            if packed {
+                // Bevy can't find the bounds of packed vertices, so give it the chunk's
+                submesh_commands.insert(Aabb::from_min_max(
+                    Vec3::splat(-HALF_SIZE),
+                    Vec3::splat(CHUNK_SIZE as f32 - HALF_SIZE),
+                ));
+            }
+            submesh_commands.insert(NotShadowCaster);
+            commands.entity(self.entity).add_child(entity);
+            self.submeshes
+                .insert(submesh.material, (entity, mesh_handle));
+            spawned.push(entity);
+        }
+        // Materials the chunk no longer has blocks of
+        self.submeshes.retain(|material, (entity, _)| {
+            let drawn = submeshes
+                .iter()
+                .any(|submesh| submesh.material == *material);
+            if !drawn {
+                commands.entity(*entity).despawn_recursive();
+            }
+            drawn
+        });
+        spawned
+    }
+
+    /// Despawn the children drawing the submeshes, so they're spawned again with the
+    /// materials of the next update
+    pub fn clear_submeshes(&mut self, commands: &mut Commands) {
+        for (_, (entity, _)) in self.submeshes.drain() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct ChunkManager {
-    chunks: HashMap<IVec3, Chunk>,
-    meshes: HashMap<IVec3, Option<Mesh>>,
+    chunks: HashMap<I64Vec3, Chunk>,
+    meshes: HashMap<I64Vec3, Option<Vec<Submesh>>>,
+    mesh_lods: HashMap<I64Vec3, MeshLod>,
 
-    chunk_load_list: VecDeque<IVec3>,
-    chunk_rebuild_list: VecDeque<IVec3>,
-    chunk_unload_list: VecDeque<IVec3>,
+    chunk_load_list: VecDeque<I64Vec3>,
+    chunk_rebuild_list: VecDeque<I64Vec3>,
+    chunk_unload_list: VecDeque<I64Vec3>,
 
-    mesh_load_list: VecDeque<IVec3>,
-    mesh_unload_list: VecDeque<IVec3>,
+    mesh_load_list: VecDeque<I64Vec3>,
+    mesh_unload_list: VecDeque<I64Vec3>,
 
-    mesh_render_list: VecDeque<IVec3>,
-    rendered_meshes: HashMap<IVec3, Entity>,
+    mesh_render_list: VecDeque<I64Vec3>,
+    rendered_meshes: HashMap<I64Vec3, RenderedChunk>,
 
     render_distance: i32,
+    /// Chunk the render and physics space is centered on, see `floating_origin`
+    origin: I64Vec3,
+    /// Whether the origin follows the camera, or stays at the world's own origin
+    pub floating_origin: bool,
+    /// Distances, in chunks from the camera, where meshes drop to the next level of detail
+    pub lod_distances: [i32; MAX_LOD_LEVEL as usize],
+    camera_chunk_pos: I64Vec3,
+    meshing_mode: MeshingMode,
+    vertex_format: ChunkVertexFormat,
+    chunk_transition: ChunkTransition,
+    block_textures: BlockTextures,
+    block_materials: BlockMaterials,
+    tint_overrides: TintOverrides,
+    /// How chunks are meshed next to neighbours that aren't loaded
+    missing_neighbours: MissingNeighbours,
+    /// Chunks whose meshing was deferred for a missing neighbour, queued again when one of
+    /// their neighbours loads instead of on every frame
+    waiting_for_neighbours: HashSet<I64Vec3>,
+    /// Whether chunks the camera can't see into are hidden, see `visible_chunks`
+    pub occlusion_culling: bool,
+
+    edited_voxels: Vec<I64Vec3>,
+
+    generator: WorldGenerator,
+    structures: Vec<StructureTemplate>,
+    structure_anchors: StructureAnchors,
 
     pub spritesheet_handle: Handle<Image>,
-    pub material_handle: Handle<StandardMaterial>,
+    /// Glow of the spritesheet's emissive sprites, see `emissive_map`
+    pub emissive_map_handle: Handle<Image>,
+    /// Normals and surface of the spritesheet's sprites, see `VoxelMaterial`
+    pub normal_map_handle: Handle<Image>,
+    pub surface_map_handle: Handle<Image>,
 }
 
 impl Default for ChunkManager {
@@ -67,22 +207,49 @@ impl ChunkManager {
         Self {
             chunks: HashMap::with_capacity(MAX_CHUNKS),
             meshes: HashMap::with_capacity(MAX_MESHES),
-            chunk_load_list: VecDeque::<IVec3>::with_capacity(MAX_CHUNK_LOAD_LIST),
-            chunk_rebuild_list: VecDeque::<IVec3>::with_capacity(MAX_CHUNK_REBUILD_LIST),
-            chunk_unload_list: VecDeque::<IVec3>::with_capacity(MAX_CHUNK_UNLOAD_LIST),
-            mesh_load_list: VecDeque::<IVec3>::with_capacity(MAX_MESH_LOAD_LIST),
-            mesh_unload_list: VecDeque::<IVec3>::with_capacity(MAX_MESH_UNLOAD_LIST),
-            mesh_render_list: VecDeque::<IVec3>::with_capacity(MAX_MESHES_TO_RENDER_LIST),
+            mesh_lods: HashMap::with_capacity(MAX_MESHES),
+            chunk_load_list: VecDeque::<I64Vec3>::with_capacity(MAX_CHUNK_LOAD_LIST),
+            chunk_rebuild_list: VecDeque::<I64Vec3>::with_capacity(MAX_CHUNK_REBUILD_LIST),
+            chunk_unload_list: VecDeque::<I64Vec3>::with_capacity(MAX_CHUNK_UNLOAD_LIST),
+            mesh_load_list: VecDeque::<I64Vec3>::with_capacity(MAX_MESH_LOAD_LIST),
+            mesh_unload_list: VecDeque::<I64Vec3>::with_capacity(MAX_MESH_UNLOAD_LIST),
+            mesh_render_list: VecDeque::<I64Vec3>::with_capacity(MAX_MESHES_TO_RENDER_LIST),
             rendered_meshes: HashMap::with_capacity(MAX_MESHES),
             render_distance: DEFAULT_RENDER_DISTANCE,
+            origin: I64Vec3::ZERO,
+            floating_origin: true,
+            lod_distances: DEFAULT_LOD_DISTANCES,
+            camera_chunk_pos: I64Vec3::ZERO,
+            meshing_mode: MeshingMode::default(),
+            vertex_format: ChunkVertexFormat::default(),
+            chunk_transition: ChunkTransition::default(),
+            block_textures: BlockTextures::default(),
+            block_materials: BlockMaterials::default(),
+            tint_overrides: TintOverrides::default(),
+            missing_neighbours: MissingNeighbours::default(),
+            waiting_for_neighbours: HashSet::new(),
+            occlusion_culling: true,
+            edited_voxels: Vec::new(),
+            generator: WorldGenerator::new(&WorldGenSettings::default()),
+            structures: Vec::new(),
+            structure_anchors: StructureAnchors::default(),
             spritesheet_handle: Handle::<Image>::weak(HandleId::Id(Uuid::nil(), 0)),
-            material_handle: Handle::<StandardMaterial>::weak(HandleId::Id(Uuid::nil(), 0)),
+            emissive_map_handle: Handle::<Image>::weak(HandleId::Id(Uuid::nil(), 0)),
+            normal_map_handle: Handle::<Image>::weak(HandleId::Id(Uuid::nil(), 0)),
+            surface_map_handle: Handle::<Image>::weak(HandleId::Id(Uuid::nil(), 0)),
         }
     }
 
+    /// A chunk manager generating chunks with the given settings, for use outside of the game
+    pub fn with_world_gen_settings(settings: &WorldGenSettings) -> Self {
+        let mut chunk_manager = ChunkManager::new();
+        chunk_manager.generator = WorldGenerator::new(settings);
+        chunk_manager
+    }
+
     /// If voxel_pos are outside of the given voxel, step to the adjacent voxel
     /// in that direction, and update the positions
-    pub fn make_coords_valid(chunk_pos: &mut IVec3, voxel_pos: &mut IVec3) {
+    pub fn make_coords_valid(chunk_pos: &mut I64Vec3, voxel_pos: &mut IVec3) {
         let chunk_size = CHUNK_SIZE as i32;
         // Right
         while voxel_pos.x >= chunk_size {
@@ -116,11 +283,25 @@ impl ChunkManager {
         }
     }
 
+    /// Split a world voxel position into the chunk position and the voxel position within that chunk
+    pub fn world_to_chunk_coords(world_pos: &I64Vec3) -> (I64Vec3, IVec3) {
+        let chunk_size = CHUNK_SIZE as i64;
+        (
+            world_pos.div_euclid(chunk_size),
+            world_pos.rem_euclid(chunk_size),
+        )
+    }
+
+    /// World voxel position of the voxel at `voxel_pos` in the chunk
+    pub fn chunk_to_world_coords(chunk_pos: &I64Vec3, voxel_pos: &IVec3) -> I64Vec3 {
+        *chunk_pos * CHUNK_SIZE as i64 + *voxel_pos
+    }
+
     pub fn get_voxel(
         &self,
-        chunk_pos: &IVec3,
+        chunk_pos: &I64Vec3,
         voxel_pos: &IVec3,
-    ) -> Result<(&Voxel, IVec3), ChunkError> {
+    ) -> Result<(&Voxel, I64Vec3), ChunkError> {
         let mut new_chunk_pos = *chunk_pos;
         let mut new_voxel_pos = *voxel_pos;
         ChunkManager::make_coords_valid(&mut new_chunk_pos, &mut new_voxel_pos);
@@ -135,18 +316,24 @@ impl ChunkManager {
         Err(ChunkError::NoChunk)
     }
 
+    pub fn get_world_voxel(&self, world_pos: &I64Vec3) -> Result<&Voxel, ChunkError> {
+        let (chunk_pos, voxel_pos) = ChunkManager::world_to_chunk_coords(world_pos);
+        Ok(self.get_voxel(&chunk_pos, &voxel_pos)?.0)
+    }
+
+    #[allow(clippy::type_complexity)]
     pub fn get_adjacent_voxels(
         &self,
-        chunk_pos: &IVec3,
+        chunk_pos: &I64Vec3,
         voxel_pos: &IVec3,
     ) -> Result<
         (
-            (&Voxel, IVec3),
-            (&Voxel, IVec3),
-            (&Voxel, IVec3),
-            (&Voxel, IVec3),
-            (&Voxel, IVec3),
-            (&Voxel, IVec3),
+            (&Voxel, I64Vec3),
+            (&Voxel, I64Vec3),
+            (&Voxel, I64Vec3),
+            (&Voxel, I64Vec3),
+            (&Voxel, I64Vec3),
+            (&Voxel, I64Vec3),
         ),
         ChunkError,
     > {
@@ -163,9 +350,9 @@ impl ChunkManager {
     pub fn get_adjacent_voxel(
         &self,
         side: Side,
-        chunk_pos: &IVec3,
+        chunk_pos: &I64Vec3,
         voxel_pos: &IVec3,
-    ) -> Result<(&Voxel, IVec3), ChunkError> {
+    ) -> Result<(&Voxel, I64Vec3), ChunkError> {
         let (x, y, z) = (voxel_pos.x, voxel_pos.y, voxel_pos.z);
         Ok(match side {
             Side::Right => self.get_voxel(chunk_pos, &IVec3::new(x + 1, y, z))?,
@@ -177,13 +364,18 @@ impl ChunkManager {
         })
     }
 
-    pub fn get_chunk(&mut self, chunk_pos: &IVec3) -> Option<&mut Chunk> {
+    pub fn get_chunk(&mut self, chunk_pos: &I64Vec3) -> Option<&mut Chunk> {
         self.chunks.get_mut(chunk_pos)
     }
 
+    pub fn chunk(&self, chunk_pos: &I64Vec3) -> Option<&Chunk> {
+        self.chunks.get(chunk_pos)
+    }
+
+    #[allow(clippy::type_complexity)]
     pub fn get_adjacent_chunks(
         &self,
-        chunk_pos: &IVec3,
+        chunk_pos: &I64Vec3,
     ) -> (
         Option<&Chunk>,
         Option<&Chunk>,
@@ -193,15 +385,72 @@ impl ChunkManager {
         Option<&Chunk>,
     ) {
         let (x, y, z) = (chunk_pos.x, chunk_pos.y, chunk_pos.z);
-        let right = self.chunks.get(&IVec3::new(x + 1, y, z));
-        let left = self.chunks.get(&IVec3::new(x - 1, y, z));
-        let top = self.chunks.get(&IVec3::new(x, y + 1, z));
-        let bottom = self.chunks.get(&IVec3::new(x, y - 1, z));
-        let front = self.chunks.get(&IVec3::new(x, y, z + 1));
-        let back = self.chunks.get(&IVec3::new(x, y, z - 1));
+        let right = self.chunks.get(&I64Vec3::new(x + 1, y, z));
+        let left = self.chunks.get(&I64Vec3::new(x - 1, y, z));
+        let top = self.chunks.get(&I64Vec3::new(x, y + 1, z));
+        let bottom = self.chunks.get(&I64Vec3::new(x, y - 1, z));
+        let front = self.chunks.get(&I64Vec3::new(x, y, z + 1));
+        let back = self.chunks.get(&I64Vec3::new(x, y, z - 1));
         (right, left, top, bottom, front, back)
     }
 
+    /// Templates of the structures placed in the chunks generated from now on
+    pub fn set_structures(&mut self, structures: Vec<StructureTemplate>) {
+        self.structures = structures;
+        self.structure_anchors.clear();
+    }
+
+    /// Draw the chunks with new settings. The world stays as it is, the meshed chunks are
+    /// queued for a rebuild if they're meshed differently
+    pub fn set_render_settings(&mut self, settings: &RenderSettings, mut commands: Commands) {
+        // Other meshes may be drawn by another kind of material, so the submeshes are
+        // spawned again
+        let new_format =
+            self.meshing_mode != settings.meshing || self.vertex_format != settings.vertex_format;
+        let remesh = new_format || self.missing_neighbours != settings.missing_neighbours;
+        self.meshing_mode = settings.meshing;
+        self.vertex_format = settings.vertex_format;
+        self.missing_neighbours = settings.missing_neighbours;
+        self.chunk_transition = settings.chunk_transition;
+        if !remesh {
+            return;
+        }
+
+        if new_format {
+            for rendered in self.rendered_meshes.values_mut() {
+                rendered.clear_submeshes(&mut commands);
+            }
+        }
+        // Chunks waiting for their neighbours are tried again by `update_visible`
+        self.waiting_for_neighbours.clear();
+        let meshed: Vec<I64Vec3> = self.meshes.keys().copied().collect();
+        for chunk_pos in meshed {
+            self.queue_rebuild(chunk_pos);
+        }
+    }
+
+    /// Use new settings for generating chunks, dropping everything that was generated
+    /// with the old ones so it gets loaded again
+    pub fn set_world_gen_settings(&mut self, settings: &WorldGenSettings, mut commands: Commands) {
+        self.generator = WorldGenerator::new(settings);
+        self.structure_anchors.clear();
+
+        for (_, rendered) in self.rendered_meshes.drain() {
+            commands.entity(rendered.entity).despawn_recursive();
+        }
+        self.chunks.clear();
+        self.meshes.clear();
+        self.mesh_lods.clear();
+        self.chunk_load_list.clear();
+        self.chunk_rebuild_list.clear();
+        self.chunk_unload_list.clear();
+        self.mesh_load_list.clear();
+        self.mesh_unload_list.clear();
+        self.mesh_render_list.clear();
+        self.waiting_for_neighbours.clear();
+        self.edited_voxels.clear();
+    }
+
     pub fn load_chunks(&mut self) {
         let mut chunks_loaded = 0;
         while let Some(chunk_pos) = self.chunk_load_list.pop_front() {
@@ -209,14 +458,10 @@ impl ChunkManager {
                 break;
             }
 
-            let mut chunk: Chunk = Chunk::new();
-            chunk.setup_perlin(chunk_pos, 1337);
-
-            self.chunks.insert(chunk_pos, chunk);
+            self.load_chunk(chunk_pos);
             // println!(
-            //     " + Chunk {} loaded, empty: {} (Total: {})",
+            //     " + Chunk {} loaded (Total: {})",
             //     chunk_pos,
-            //     chunk.empty,
             //     self.chunks.len()
             // );
 
@@ -227,11 +472,65 @@ impl ChunkManager {
         }
     }
 
+    /// Generate the chunk right away, along with the structures that reach into it
+    pub fn load_chunk(&mut self, chunk_pos: I64Vec3) {
+        let mut chunk: Chunk = Chunk::new();
+        chunk.setup_generated(chunk_pos, &self.generator);
+
+        // Structures anchored in the chunks around it too, as the chunk takes in its part
+        // of them itself, whether their anchors are loaded or not
+        let structure_voxels = structures::chunk_structure_voxels(
+            &self.structures,
+            &self.generator,
+            &mut self.structure_anchors,
+            chunk_pos,
+        );
+        if !structure_voxels.is_empty() {
+            for (index, voxel) in structure_voxels {
+                if let Some(chunk_voxel) = chunk.get_mut_voxel(index) {
+                    *chunk_voxel = voxel;
+                }
+            }
+            chunk.check_empty();
+        }
+        chunk.update_connectivity(&self.block_materials);
+
+        self.chunks.insert(chunk_pos, chunk);
+        self.queue_waiting_neighbours(chunk_pos);
+    }
+
+    /// Queue the meshing of the chunks around a newly loaded one that were waiting on it.
+    /// Those still missing another neighbour go back to waiting when they're tried. Unless
+    /// meshing is deferred, the ones meshed without it are meshed again to fix their border
+    fn queue_waiting_neighbours(&mut self, chunk_pos: I64Vec3) {
+        for index in 0..27 {
+            let neighbour_pos = chunk_pos + neighbour_offset(index);
+            if !self.waiting_for_neighbours.remove(&neighbour_pos) {
+                if self.missing_neighbours != MissingNeighbours::Defer
+                    && neighbour_pos != chunk_pos
+                    && self.meshes.contains_key(&neighbour_pos)
+                {
+                    self.queue_rebuild(neighbour_pos);
+                }
+                continue;
+            }
+            if self.meshes.contains_key(&neighbour_pos) {
+                self.queue_rebuild(neighbour_pos);
+            } else if !self.mesh_load_list.contains(&neighbour_pos)
+                && self.mesh_load_list.len() < MAX_MESH_LOAD_LIST
+            {
+                self.update_voxel_data(neighbour_pos);
+                self.mesh_load_list.push_back(neighbour_pos);
+            }
+        }
+    }
+
     pub fn unload_chunks(&mut self) {
         let mut chunks_unloaded = 0;
         while let Some(chunk_pos) = self.chunk_unload_list.pop_front() {
             // println!(" - Chunk {} unloaded", chunk_pos);
             self.chunks.remove(&chunk_pos);
+            self.waiting_for_neighbours.remove(&chunk_pos);
 
             chunks_unloaded += 1;
             if chunks_unloaded >= MAX_UNLOAD_CHUNKS_PER_FRAME {
@@ -244,26 +543,37 @@ impl ChunkManager {
         let mut chunks_rebuilt = 0;
         while let Some(chunk_pos) = self.chunk_rebuild_list.pop_front() {
             if let Some(chunk) = self.chunks.get(&chunk_pos) {
-                // First remove the mesh from our world
-                if let Some(entity) = self.rendered_meshes.remove(&chunk_pos) {
-                    // println!(" - Entity removed");
-                    commands.entity(entity).despawn();
-                }
+                // Mesh first, so the old mesh stays while waiting for the neighbours
+                let lod = self.mesh_lod(&chunk_pos);
+                let mesh = if chunk.empty {
+                    None
+                } else {
+                    let Some(mesh) = self.build_mesh(chunk, &chunk_pos, &lod) else {
+                        self.waiting_for_neighbours.insert(chunk_pos);
+                        continue;
+                    };
+                    Some(mesh)
+                };
 
-                // Empty chunks have no mesh, skip
-                if chunk.empty == true {
+                // Empty chunks have no mesh, remove it from our world. Otherwise `render`
+                // updates the chunk's entity in place
+                let Some(mesh) = mesh else {
+                    if let Some(rendered) = self.rendered_meshes.remove(&chunk_pos) {
+                        commands.entity(rendered.entity).despawn_recursive();
+                    }
                     self.meshes.insert(chunk_pos, None);
+                    self.mesh_lods.remove(&chunk_pos);
                     continue;
-                }
+                };
 
-                let mesh = chunk_mesh_builder::build_mesh(self, chunk, &chunk_pos);
                 self.meshes.insert(chunk_pos, Some(mesh));
+                self.mesh_lods.insert(chunk_pos, lod);
                 if !self.mesh_render_list.contains(&chunk_pos) {
                     self.mesh_render_list.push_back(chunk_pos);
                 }
 
                 // Update the data of all our neighbors?
-                // let neighbour_chunk_pos: Vec<IVec3> = [
+                // let neighbour_chunk_pos: Vec<I64Vec3> = [
                 //     IVec3::X,
                 //     IVec3::NEG_X,
                 //     IVec3::Y,
@@ -301,13 +611,19 @@ impl ChunkManager {
             // We can only load mesh if we have the chunk
             if let Some(chunk) = self.chunks.get(&chunk_pos) {
                 // Empty chunks have no mesh, skip
-                if chunk.empty == true {
+                if chunk.empty {
                     self.meshes.insert(chunk_pos, None);
                     continue;
                 }
 
-                let mesh = chunk_mesh_builder::build_mesh(self, chunk, &chunk_pos);
+                // Deferred meshes get queued again when a missing neighbour loads
+                let lod = self.mesh_lod(&chunk_pos);
+                let Some(mesh) = self.build_mesh(chunk, &chunk_pos, &lod) else {
+                    self.waiting_for_neighbours.insert(chunk_pos);
+                    continue;
+                };
                 self.meshes.insert(chunk_pos, Some(mesh));
+                self.mesh_lods.insert(chunk_pos, lod);
                 if !self.mesh_render_list.contains(&chunk_pos) {
                     self.mesh_render_list.push_back(chunk_pos);
                 }
@@ -330,10 +646,11 @@ impl ChunkManager {
         while let Some(chunk_pos) = self.mesh_unload_list.pop_front() {
             // println!(" - Mesh {} unloaded", chunk_pos);
             self.meshes.remove(&chunk_pos);
+            self.mesh_lods.remove(&chunk_pos);
 
-            if let Some(entity) = self.rendered_meshes.remove(&chunk_pos) {
+            if let Some(rendered) = self.rendered_meshes.remove(&chunk_pos) {
                 // println!(" - Entity removed");
-                commands.entity(entity).despawn();
+                commands.entity(rendered.entity).despawn_recursive();
             }
 
             meshes_unloaded += 1;
@@ -344,12 +661,8 @@ impl ChunkManager {
     }
 
     pub fn update_visible(&mut self, camera_transform: &Transform, _camera_frustrum: &Frustum) {
-        let camera_position = camera_transform.translation;
-        let camera_chunk_pos: IVec3 = IVec3::new(
-            (camera_position.x / CHUNK_SIZE as f32) as i32,
-            (camera_position.y / CHUNK_SIZE as f32) as i32,
-            (camera_position.z / CHUNK_SIZE as f32) as i32,
-        );
+        let camera_chunk_pos = self.chunk_pos_at(camera_transform.translation);
+        self.camera_chunk_pos = camera_chunk_pos;
 
         // Look for Chunks within render distance
         for x in -self.render_distance..(self.render_distance + 1) {
@@ -367,50 +680,50 @@ impl ChunkManager {
                     }
 
                     if !self.meshes.contains_key(&chunk_pos)
+                        && !self.waiting_for_neighbours.contains(&chunk_pos)
                         && !self.mesh_load_list.contains(&chunk_pos)
                         && self.mesh_load_list.len() < MAX_MESH_LOAD_LIST
                     {
-                        // Queue mesh if all adjacent chunk's data are loaded
-                        let missing_neighbour_data = [
-                            IVec3::X,
-                            IVec3::NEG_X,
-                            IVec3::Y,
-                            IVec3::NEG_Y,
-                            IVec3::Z,
-                            IVec3::NEG_Z,
-                        ]
-                        .iter_mut()
-                        .map(|v| *v + chunk_pos)
-                        .any(|v| !self.chunks.contains_key(&v));
+                        // Queue mesh once the chunk's data is loaded, and all adjacent chunk's
+                        // data too unless missing neighbours are meshed as air or solid
+                        let missing_data = !self.chunks.contains_key(&chunk_pos)
+                            || self.missing_neighbours == MissingNeighbours::Defer
+                                && [
+                                    IVec3::X,
+                                    IVec3::NEG_X,
+                                    IVec3::Y,
+                                    IVec3::NEG_Y,
+                                    IVec3::Z,
+                                    IVec3::NEG_Z,
+                                ]
+                                .iter()
+                                .map(|v| chunk_pos + *v)
+                                .any(|v| !self.chunks.contains_key(&v));
 
                         // Queue mesh
-                        if !missing_neighbour_data {
-                            // Copy the chunk, update voxel data, and put it back.. Not very effective
-                            if let Some(chunk) = self.chunks.get(&chunk_pos) {
-                                let mut updated_chunk = *chunk;
-                                updated_chunk.update_voxel_data(self, &chunk_pos);
-                                self.chunks.insert(chunk_pos, updated_chunk);
-                            }
+                        if !missing_data {
+                            self.update_voxel_data(chunk_pos);
                             self.mesh_load_list.push_back(chunk_pos);
                         }
                     }
+
+                    // Mesh again when the camera moved the chunk, or one of its neighbours,
+                    // to another level of detail
+                    if let Some(lod) = self.mesh_lods.get(&chunk_pos) {
+                        if *lod != self.mesh_lod(&chunk_pos)
+                            && !self.waiting_for_neighbours.contains(&chunk_pos)
+                            && self.chunk_rebuild_list.len() < MAX_CHUNK_REBUILD_LIST
+                        {
+                            self.queue_rebuild(chunk_pos);
+                        }
+                    }
                 }
             }
         }
 
         // Unload chunks outside of render distance
-        let min_pos = camera_chunk_pos
-            - IVec3::new(
-                self.render_distance,
-                self.render_distance,
-                self.render_distance,
-            );
-        let max_pos = camera_chunk_pos
-            + IVec3::new(
-                self.render_distance,
-                self.render_distance,
-                self.render_distance,
-            );
+        let min_pos = camera_chunk_pos - IVec3::splat(self.render_distance);
+        let max_pos = camera_chunk_pos + IVec3::splat(self.render_distance);
 
         let chunk_pos_outside: Vec<_> = self
             .chunks
@@ -444,34 +757,52 @@ impl ChunkManager {
     pub fn render(&mut self, mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
         let mut rendered_meshes = 0;
         while let Some(chunk_pos) = self.mesh_render_list.pop_front() {
-            if self.rendered_meshes.len() >= MAX_MESHES {
+            if self.rendered_meshes.len() >= MAX_MESHES
+                && !self.rendered_meshes.contains_key(&chunk_pos)
+            {
                 self.mesh_render_list.push_back(chunk_pos);
                 return;
             }
 
             if let Some(mesh_option) = self.meshes.get(&chunk_pos) {
-                if let Some(mesh) = mesh_option {
-                    if mesh.count_vertices() == 0 {
+                if let Some(submeshes) = mesh_option {
+                    if submeshes.is_empty() {
+                        if let Some(rendered) = self.rendered_meshes.remove(&chunk_pos) {
+                            commands.entity(rendered.entity).despawn_recursive();
+                        }
                         continue;
                     };
-                    let Some(collider) = Collider::from_bevy_mesh(mesh, &ComputedColliderShape::TriMesh) else { continue; };
-                    let chunk_entity = commands
-                        .spawn((
-                            MaterialMeshBundle {
-                                mesh: meshes.add(mesh.clone()),
-                                material: self.material_handle.clone(),
-                                transform: Transform::from_xyz(
-                                    chunk_pos.x as f32 * CHUNK_SIZE as f32,
-                                    chunk_pos.y as f32 * CHUNK_SIZE as f32,
-                                    chunk_pos.z as f32 * CHUNK_SIZE as f32,
-                                ),
-                                ..default()
-                            },
-                            NotShadowCaster,
-                        ))
-                        .insert(collider)
-                        .id();
-                    self.rendered_meshes.insert(chunk_pos, chunk_entity);
+
+                    // Rebuilt chunks keep their entities and mesh assets, so they don't flicker
+                    let mut spawned = false;
+                    let translation = self.chunk_translation(chunk_pos);
+                    let rendered = self.rendered_meshes.entry(chunk_pos).or_insert_with(|| {
+                        spawned = true;
+                        RenderedChunk::spawn(
+                            &mut commands,
+                            Transform::from_translation(translation),
+                        )
+                    });
+                    // Chunks of nothing but fluids have nothing to collide with
+                    match chunk_collider(submeshes) {
+                        Some(collider) => commands.entity(rendered.entity).insert(collider),
+                        None => commands.entity(rendered.entity).remove::<Collider>(),
+                    };
+
+                    let spawned_submeshes = rendered.update_submeshes(
+                        &mut commands,
+                        &mut meshes,
+                        &self.block_materials,
+                        submeshes,
+                    );
+                    // Only new chunks slide in, not submeshes added to chunks already in place
+                    if spawned && self.chunk_transition == ChunkTransition::SlideIn {
+                        for entity in spawned_submeshes {
+                            commands
+                                .entity(entity)
+                                .insert((SlidingIn::default(), SlidingIn::start_transform()));
+                        }
+                    }
 
                     rendered_meshes += 1;
                 }
@@ -482,93 +813,411 @@ impl ChunkManager {
         }
     }
 
-    pub fn get_chunk_pos_by_entity(&self, entity: Entity) -> Option<IVec3> {
-        self.rendered_meshes
-            .iter()
-            .find_map(|(key, val)| if *val == entity { Some(*key) } else { None })
+    pub fn get_chunk_pos_by_entity(&self, entity: Entity) -> Option<I64Vec3> {
+        self.rendered_meshes.iter().find_map(|(key, val)| {
+            if val.entity == entity {
+                Some(*key)
+            } else {
+                None
+            }
+        })
     }
 
-    pub fn get_voxel_position(&self, chunk_pos: &IVec3, hit_pos: &Vec3) -> Option<Vec3> {
+    pub fn get_voxel_position(&self, chunk_pos: &I64Vec3, hit_pos: &Vec3) -> Option<Vec3> {
         // Convert hit position to voxel position, and find the correct chunk
+        let chunk_offset = (*chunk_pos - self.origin).as_ivec3() * CHUNK_SIZE as i32;
         let mut voxel_pos = IVec3::new(
-            hit_pos.x.round() as i32 - (chunk_pos.x * CHUNK_SIZE as i32),
-            hit_pos.y.round() as i32 - (chunk_pos.y * CHUNK_SIZE as i32),
-            hit_pos.z.round() as i32 - (chunk_pos.z * CHUNK_SIZE as i32),
+            hit_pos.x.round() as i32 - chunk_offset.x,
+            hit_pos.y.round() as i32 - chunk_offset.y,
+            hit_pos.z.round() as i32 - chunk_offset.z,
         );
         let mut new_chunk_pos = *chunk_pos;
         ChunkManager::make_coords_valid(&mut new_chunk_pos, &mut voxel_pos);
 
-        Some(Vec3::new(
-            new_chunk_pos.x as f32 * CHUNK_SIZE as f32 + voxel_pos.x as f32,
-            new_chunk_pos.y as f32 * CHUNK_SIZE as f32 + voxel_pos.y as f32,
-            new_chunk_pos.z as f32 * CHUNK_SIZE as f32 + voxel_pos.z as f32,
-        ))
+        Some(self.world_to_render(ChunkManager::chunk_to_world_coords(
+            &new_chunk_pos,
+            &voxel_pos,
+        )))
     }
 
     pub fn update_voxel(
         &mut self,
-        chunk_pos: &IVec3,
+        chunk_pos: &I64Vec3,
         hit_pos: &Vec3,
         active: bool,
         voxel_type: VoxelType,
     ) {
         // Convert hit position to voxel position, and find the correct chunk
+        let chunk_offset = (*chunk_pos - self.origin).as_ivec3() * CHUNK_SIZE as i32;
         let mut voxel_pos = IVec3::new(
-            hit_pos.x.round() as i32 - (chunk_pos.x * CHUNK_SIZE as i32),
-            hit_pos.y.round() as i32 - (chunk_pos.y * CHUNK_SIZE as i32),
-            hit_pos.z.round() as i32 - (chunk_pos.z * CHUNK_SIZE as i32),
+            hit_pos.x.round() as i32 - chunk_offset.x,
+            hit_pos.y.round() as i32 - chunk_offset.y,
+            hit_pos.z.round() as i32 - chunk_offset.z,
         );
         let mut new_chunk_pos = *chunk_pos;
         ChunkManager::make_coords_valid(&mut new_chunk_pos, &mut voxel_pos);
 
-        let Some(chunk) = self.chunks.get(&new_chunk_pos) else { return; };
-        let mut updated_chunk = chunk.clone();
+        let world_pos = ChunkManager::chunk_to_world_coords(&new_chunk_pos, &voxel_pos);
+        let voxel = Voxel {
+            voxel_type,
+            ..Voxel::new(active)
+        };
+        self.update_voxels(&[(world_pos, voxel)]);
+    }
 
-        println!(
-            "Updating Voxel pos {} in chunk {}",
-            voxel_pos, new_chunk_pos
-        );
-        let voxel_index = Chunk::get_index(&voxel_pos);
-        if let Some(mut voxel) = updated_chunk.get_mut_voxel(voxel_index) {
-            // Update the voxel, and set this as our new chunk data
-            voxel.active = active;
-            voxel.voxel_type = voxel_type;
-            self.chunks.insert(new_chunk_pos, updated_chunk);
-
-            // Then let the chunk look over its voxels before updating it again and queueing to rebuild
-            updated_chunk.update_voxel_data(self, &new_chunk_pos);
-            self.chunks.insert(new_chunk_pos, updated_chunk); // Very unoptimized to have to set it twice
-            self.chunk_rebuild_list.push_back(new_chunk_pos);
+    /// Apply a batch of voxel edits given in world voxel coordinates. Each edited chunk
+    /// is only updated and queued for rebuild once, no matter how many of its voxels changed
+    pub fn update_voxels(&mut self, edits: &[(I64Vec3, Voxel)]) {
+        let mut edited_chunks = HashMap::<I64Vec3, Chunk>::new();
+        for (world_pos, new_voxel) in edits {
+            let (chunk_pos, voxel_pos) = ChunkManager::world_to_chunk_coords(world_pos);
+            let chunk = match edited_chunks.entry(chunk_pos) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let Some(chunk) = self.chunks.get(&chunk_pos) else { continue; };
+                    entry.insert(*chunk)
+                }
+            };
+            let Some(voxel) = chunk.get_mut_voxel(Chunk::get_index(&voxel_pos)) else { continue; };
+            // Fluids flowing in and out of a voxel, or blocks swapped for others, leave the
+            // ground's density as it was, so the smooth surface stays in place
+            let density = if voxel.is_solid() == new_voxel.is_solid() {
+                voxel.density
+            } else {
+                new_voxel.density
+            };
+            *voxel = Voxel {
+                density,
+                ..*new_voxel
+            };
+            self.edited_voxels.push(*world_pos);
 
             // Update neighbor chunks if we're next to any
-            if let Ok((
-                (_, right_chunk_pos),
-                (_, left_chunk_pos),
-                (_, top_chunk_pos),
-                (_, bottom_chunk_pos),
-                (_, front_chunk_pos),
-                (_, back_chunk_pos),
-            )) = self.get_adjacent_voxels(&new_chunk_pos, &voxel_pos)
-            {
-                if right_chunk_pos != new_chunk_pos {
-                    self.chunk_rebuild_list.push_back(right_chunk_pos);
+            for offset in [
+                IVec3::X,
+                IVec3::NEG_X,
+                IVec3::Y,
+                IVec3::NEG_Y,
+                IVec3::Z,
+                IVec3::NEG_Z,
+            ] {
+                let (neighbour_chunk_pos, _) =
+                    ChunkManager::world_to_chunk_coords(&(*world_pos + offset));
+                if neighbour_chunk_pos != chunk_pos {
+                    self.queue_rebuild(neighbour_chunk_pos);
                 }
-                if left_chunk_pos != new_chunk_pos {
-                    self.chunk_rebuild_list.push_back(left_chunk_pos);
-                }
-                if top_chunk_pos != new_chunk_pos {
-                    self.chunk_rebuild_list.push_back(top_chunk_pos);
-                }
-                if bottom_chunk_pos != new_chunk_pos {
-                    self.chunk_rebuild_list.push_back(bottom_chunk_pos);
+            }
+        }
+
+        // Set all the edited chunks first, so they see each other's changes when looking over their voxels
+        for (chunk_pos, chunk) in edited_chunks.iter() {
+            self.chunks.insert(*chunk_pos, *chunk);
+        }
+        for (chunk_pos, mut chunk) in edited_chunks {
+            chunk.update_voxel_data(self, &chunk_pos);
+            self.chunks.insert(chunk_pos, chunk);
+            self.queue_rebuild(chunk_pos);
+        }
+    }
+
+    /// Update the voxels of a loaded chunk that depend on its surroundings, like grass
+    /// covered by other blocks turning into dirt
+    pub fn update_voxel_data(&mut self, chunk_pos: I64Vec3) {
+        // Copy the chunk, update voxel data, and put it back.. Not very effective
+        if let Some(chunk) = self.chunks.get(&chunk_pos) {
+            let mut updated_chunk = *chunk;
+            updated_chunk.update_voxel_data(self, &chunk_pos);
+            self.chunks.insert(chunk_pos, updated_chunk);
+        }
+    }
+
+    /// World voxel positions edited since the last call
+    pub fn take_edited_voxels(&mut self) -> Vec<I64Vec3> {
+        std::mem::take(&mut self.edited_voxels)
+    }
+
+    pub fn generator(&self) -> &WorldGenerator {
+        &self.generator
+    }
+
+    pub fn block_textures(&self) -> &BlockTextures {
+        &self.block_textures
+    }
+
+    pub fn block_materials(&self) -> &BlockMaterials {
+        &self.block_materials
+    }
+
+    /// Draw the blocks with other materials. Which chunks can be seen through others may
+    /// change with them, and the meshed chunks are queued for a rebuild. Their submeshes are
+    /// spawned again, as they may be drawn by another kind of material
+    pub fn set_block_materials(&mut self, block_materials: BlockMaterials, mut commands: Commands) {
+        self.block_materials = block_materials;
+        for chunk in self.chunks.values_mut() {
+            chunk.update_connectivity(&self.block_materials);
+        }
+        for rendered in self.rendered_meshes.values_mut() {
+            rendered.clear_submeshes(&mut commands);
+        }
+        let meshed: Vec<I64Vec3> = self.meshes.keys().copied().collect();
+        for chunk_pos in meshed {
+            self.queue_rebuild(chunk_pos);
+        }
+    }
+
+    /// Mesh the blocks with other sprites. The meshed chunks are queued for a rebuild, so
+    /// they change over without disappearing
+    pub fn set_block_textures(&mut self, block_textures: BlockTextures) {
+        if self.block_textures == block_textures {
+            return;
+        }
+        self.block_textures = block_textures;
+        let meshed: Vec<I64Vec3> = self.meshes.keys().copied().collect();
+        for chunk_pos in meshed {
+            self.queue_rebuild(chunk_pos);
+        }
+    }
+
+    pub fn tint_overrides(&self) -> &TintOverrides {
+        &self.tint_overrides
+    }
+
+    /// Mesh the blocks with other tint overrides. Only the meshed chunks whose tints changed
+    /// are queued for a rebuild
+    pub fn set_tint_overrides(&mut self, tint_overrides: TintOverrides) {
+        let changed = self.tint_overrides.changed_chunks(&tint_overrides);
+        self.tint_overrides = tint_overrides;
+        for chunk_pos in changed {
+            if self.meshes.contains_key(&chunk_pos) {
+                self.queue_rebuild(chunk_pos);
+            }
+        }
+    }
+
+    /// Chunk positions and entities of the rendered chunks
+    pub fn rendered_chunks(&self) -> impl Iterator<Item = (I64Vec3, Entity)> + '_ {
+        self.rendered_meshes
+            .iter()
+            .map(|(chunk_pos, rendered)| (*chunk_pos, rendered.entity))
+    }
+
+    /// Chunks within the render distance that may be seen from the camera's chunk, found by
+    /// walking out from it through the sides each chunk connects. The walk never turns back
+    /// along a direction it already went, so it can't wind around behind walls. Chunks that
+    /// aren't loaded yet are seen through
+    pub fn visible_chunks(&self, camera_chunk_pos: I64Vec3) -> HashSet<I64Vec3> {
+        let mut visible = HashSet::new();
+        visible.insert(camera_chunk_pos);
+        // Each chunk with the side it was entered through, and a bit per side gone out of
+        let mut queue = VecDeque::new();
+        queue.push_back((camera_chunk_pos, Option::<Side>::None, 0u8));
+        while let Some((chunk_pos, entered_from, directions)) = queue.pop_front() {
+            let connectivity = self
+                .chunks
+                .get(&chunk_pos)
+                .map_or(ChunkConnectivity::ALL, |chunk| chunk.connectivity);
+            for (side, offset) in Side::ALL.into_iter().zip(NEIGHBOUR_OFFSETS) {
+                if directions & (1 << side.opposite() as u8) != 0 {
+                    continue;
                 }
-                if front_chunk_pos != new_chunk_pos {
-                    self.chunk_rebuild_list.push_back(front_chunk_pos);
+                if entered_from.is_some_and(|from| !connectivity.connects(from, side)) {
+                    continue;
                 }
-                if back_chunk_pos != new_chunk_pos {
-                    self.chunk_rebuild_list.push_back(back_chunk_pos);
+                let neighbour_pos = chunk_pos + offset;
+                let distance = (neighbour_pos - camera_chunk_pos)
+                    .as_ivec3()
+                    .abs()
+                    .max_element();
+                if distance > self.render_distance || !visible.insert(neighbour_pos) {
+                    continue;
                 }
+                queue.push_back((
+                    neighbour_pos,
+                    Some(side.opposite()),
+                    directions | 1 << side as u8,
+                ));
             }
         }
+        visible
+    }
+
+    /// Chunk the render and physics space is centered on, see `floating_origin`
+    pub fn origin(&self) -> I64Vec3 {
+        self.origin
+    }
+
+    /// Center the render space on another chunk. Only the position of chunks spawned from now
+    /// on follows, the transforms already out there have to be moved by the caller
+    pub fn set_origin(&mut self, origin: I64Vec3) {
+        self.origin = origin;
+    }
+
+    /// Translation of the chunk's entity, relative to the origin
+    pub fn chunk_translation(&self, chunk_pos: I64Vec3) -> Vec3 {
+        ((chunk_pos - self.origin).as_ivec3() * CHUNK_SIZE as i32).as_vec3()
+    }
+
+    /// Where a world voxel position is in the render space. Only the offset from the origin
+    /// goes into f32, so it's as precise anywhere in the world as it is near its middle
+    pub fn world_to_render(&self, world_pos: I64Vec3) -> Vec3 {
+        (world_pos - self.origin * CHUNK_SIZE as i64).as_vec3()
+    }
+
+    /// World position of the voxel a point in the render space lies in
+    pub fn render_to_world(&self, translation: Vec3) -> I64Vec3 {
+        self.origin * CHUNK_SIZE as i64 + translation.round().as_ivec3()
+    }
+
+    /// Chunk a point in the render space lies in
+    pub fn chunk_pos_at(&self, translation: Vec3) -> I64Vec3 {
+        self.origin + (translation / CHUNK_SIZE as f32).floor().as_ivec3()
+    }
+
+    pub fn render_distance(&self) -> i32 {
+        self.render_distance
+    }
+
+    /// Chunk the camera was in at the last `update_visible`
+    pub fn camera_chunk_pos(&self) -> I64Vec3 {
+        self.camera_chunk_pos
+    }
+
+    /// Level of detail for the chunk, from how far it is from the camera
+    pub fn lod_level(&self, chunk_pos: &I64Vec3) -> u32 {
+        let distance = (*chunk_pos - self.camera_chunk_pos)
+            .as_ivec3()
+            .abs()
+            .max_element();
+        self.lod_distances
+            .iter()
+            .filter(|lod_distance| distance >= **lod_distance)
+            .count() as u32
+    }
+
+    fn mesh_lod(&self, chunk_pos: &I64Vec3) -> MeshLod {
+        if self.meshing_mode == MeshingMode::Smooth {
+            return MeshLod::default();
+        }
+        let level = self.lod_level(chunk_pos);
+        let mut skirts = [false; 6];
+        for (skirt, offset) in skirts.iter_mut().zip(NEIGHBOUR_OFFSETS) {
+            *skirt = self.lod_level(&(*chunk_pos + offset)) != level;
+        }
+        MeshLod { level, skirts }
+    }
+
+    /// Mesh of the chunk, or None if it has to wait for its neighbours
+    fn build_mesh(
+        &self,
+        chunk: &Chunk,
+        chunk_pos: &I64Vec3,
+        lod: &MeshLod,
+    ) -> Option<Vec<Submesh>> {
+        let padded_chunk = PaddedChunk::new(self, chunk, chunk_pos, self.missing_neighbours)?;
+        Some(self.mesh_padded_chunk(&padded_chunk, lod))
+    }
+
+    /// Mesh of a padded chunk in the configured meshing mode and vertex format, drawn with
+    /// the configured materials. Voxel bodies go through it too, so they look like the world
+    pub fn mesh_padded_chunk(&self, padded_chunk: &PaddedChunk, lod: &MeshLod) -> Vec<Submesh> {
+        match self.meshing_mode {
+            MeshingMode::Blocky => chunk_mesh_builder::build_mesh(
+                padded_chunk,
+                lod,
+                self.vertex_format,
+                &self.block_textures,
+                &self.block_materials,
+            ),
+            MeshingMode::Smooth => chunk_mesh_builder::build_smooth_mesh(
+                padded_chunk,
+                &self.block_textures,
+                &self.block_materials,
+            ),
+        }
+    }
+
+    fn queue_rebuild(&mut self, chunk_pos: I64Vec3) {
+        if self.chunks.contains_key(&chunk_pos) && !self.chunk_rebuild_list.contains(&chunk_pos) {
+            self.chunk_rebuild_list.push_back(chunk_pos);
+        }
+    }
+}
+
+/// Trimesh collider of the solid blocks of all of a chunk's submeshes, in either vertex format,
+/// or None if there are none. Fluids are left out, so things sink into them
+fn chunk_collider(submeshes: &[Submesh]) -> Option<Collider> {
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    for Submesh {
+        mesh,
+        solid_indices,
+        ..
+    } in submeshes
+    {
+        let first_index = positions.len() as u32;
+        let packed = mesh.attribute(ATTRIBUTE_PACKED_VOXEL);
+        match (packed, mesh.attribute(Mesh::ATTRIBUTE_POSITION)) {
+            (Some(VertexAttributeValues::Uint32x2(packed)), _) => {
+                positions.extend(packed.iter().map(|packed| unpack_position(*packed)));
+            }
+            (_, Some(VertexAttributeValues::Float32x3(mesh_positions))) => {
+                positions.extend(mesh_positions.iter().map(|position| Vec3::from(*position)));
+            }
+            _ => return None,
+        }
+        let mesh_indices: Vec<u32> = mesh
+            .indices()?
+            .iter()
+            .take(*solid_indices)
+            .map(|index| index as u32)
+            .collect();
+        indices.extend(mesh_indices.chunks_exact(3).map(|triangle| {
+            [triangle[0], triangle[1], triangle[2]].map(|index| first_index + index)
+        }));
+    }
+    (!indices.is_empty()).then(|| Collider::trimesh(positions, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_connectivity::tests::{sealed_chunk, tunnel_chunk};
+
+    /// The camera's chunk with sealed chunks on all sides but the right, where `right` goes
+    fn boxed_in_camera(right: Chunk) -> ChunkManager {
+        let mut chunk_manager = ChunkManager::new();
+        chunk_manager.render_distance = 2;
+        for (side, offset) in Side::ALL.into_iter().zip(NEIGHBOUR_OFFSETS) {
+            let chunk = match side {
+                Side::Right => right,
+                _ => sealed_chunk(),
+            };
+            chunk_manager.chunks.insert(offset.into(), chunk);
+        }
+        chunk_manager
+    }
+
+    #[test]
+    fn sealed_chunks_hide_everything_behind_them() {
+        let visible = boxed_in_camera(sealed_chunk()).visible_chunks(I64Vec3::ZERO);
+        let expected: HashSet<I64Vec3> = NEIGHBOUR_OFFSETS
+            .into_iter()
+            .chain([IVec3::ZERO])
+            .map(I64Vec3::from)
+            .collect();
+        assert_eq!(visible, expected);
+    }
+
+    #[test]
+    fn tunnel_shows_the_chunks_past_its_turn() {
+        let visible = boxed_in_camera(tunnel_chunk()).visible_chunks(I64Vec3::ZERO);
+        // Out of the top of the tunnel and on from there
+        assert!(visible.contains(&I64Vec3::new(1, 1, 0)));
+        assert!(visible.contains(&I64Vec3::new(1, 2, 0)));
+        assert!(visible.contains(&I64Vec3::new(2, 1, 0)));
+        // Past the tunnel's solid sides, or back over the sealed chunks
+        assert!(!visible.contains(&I64Vec3::new(2, 0, 0)));
+        assert!(!visible.contains(&I64Vec3::new(1, -1, 0)));
+        assert!(!visible.contains(&I64Vec3::new(0, 2, 0)));
     }
 }
@@ -1,67 +1,146 @@
 use bevy::{
-    prelude::{IVec3, Mesh, Vec3},
+    prelude::{IVec3, Mesh, UVec2, Vec3},
     render::{mesh::Indices, render_resource::PrimitiveTopology},
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    chunk::{Chunk, CHUNK_SIZE},
-    chunk_manager::ChunkManager,
+    block_materials::BlockMaterials,
+    chunk::CHUNK_SIZE,
     face::{Face, Side},
+    padded_chunk::PaddedChunk,
+    voxel::{BlockMaterial, Voxel, VoxelType, FULL_DENSITY},
+    voxel_material::{
+        occlusion_brightness, pack_smooth_sprites, pack_vertex, ATTRIBUTE_PACKED_VOXEL,
+        ATTRIBUTE_SMOOTH_SPRITES, NO_OCCLUSION,
+    },
+    voxel_textures::{BlockTextures, TextureRule},
+    world_coords::I64Vec3,
 };
 
-pub fn build_mesh(chunk_manager: &ChunkManager, chunk: &Chunk, chunk_pos: &IVec3) -> Mesh {
-    let mut faces = Vec::<Face>::new();
+/// Least detailed level, where cells of 8x8x8 voxels are meshed as one
+pub const MAX_LOD_LEVEL: u32 = 3;
+/// Offsets to the neighbouring chunks, in the order of `MeshLod::skirts`
+pub const NEIGHBOUR_OFFSETS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
 
-    for x in 0..CHUNK_SIZE {
-        for y in 0..CHUNK_SIZE {
-            for z in 0..CHUNK_SIZE {
-                let index: usize = Chunk::index_from(x, y, z);
+/// Cells along each side that smooth meshing can put vertices in, starting one voxel before
+/// the chunk, so the cells shared with the neighbours on the negative sides are included
+const SMOOTH_CELLS: usize = CHUNK_SIZE + 1;
+/// Density the smooth surface runs through, as a fraction of `FULL_DENSITY`
+const ISO_LEVEL: f32 = 0.5;
+/// Corners of a cell connected by its twelve edges, as bits of x, y and z
+const CELL_EDGES: [(usize, usize); 12] = [
+    (0b000, 0b100),
+    (0b010, 0b110),
+    (0b001, 0b101),
+    (0b011, 0b111),
+    (0b000, 0b010),
+    (0b100, 0b110),
+    (0b001, 0b011),
+    (0b101, 0b111),
+    (0b000, 0b001),
+    (0b100, 0b101),
+    (0b010, 0b011),
+    (0b110, 0b111),
+];
 
-                if let Some(voxel) = chunk.get_voxel(index) {
-                    if !voxel.active {
-                        continue;
-                    }
+/// How chunks are turned into meshes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshingMode {
+    /// A cube face on every side of a voxel facing an empty one, see `build_mesh`
+    #[default]
+    Blocky,
+    /// A smooth surface through the voxel densities, see `build_smooth_mesh`. Always meshed
+    /// at full detail, and drawn with a `VoxelMaterial` whatever the vertex format
+    Smooth,
+}
 
-                    let voxel_pos = IVec3::new(x as i32, y as i32, z as i32);
-                    let voxel_pos_local = Vec3::new(x as f32, y as f32, z as f32);
-
-                    /*
-                    if voxel.active {
-                        faces.push(Face::new(Side::Left, voxel_pos_local));
-                        faces.push(Face::new(Side::Bottom, voxel_pos_local));
-                        faces.push(Face::new(Side::Back, voxel_pos_local));
-                        faces.push(Face::new(Side::Right, voxel_pos_local));
-                        faces.push(Face::new(Side::Top, voxel_pos_local));
-                        faces.push(Face::new(Side::Front, voxel_pos_local));
-                    }
-                    */
-
-                    if let Ok((
-                        (right, _),
-                        (left, _),
-                        (top, _),
-                        (bottom, _),
-                        (front, _),
-                        (back, _),
-                    )) = chunk_manager.get_adjacent_voxels(chunk_pos, &voxel_pos)
-                    {
-                        if !left.active {
-                            faces.push(Face::new(Side::Left, voxel_pos_local, voxel.voxel_type));
-                        }
-                        if !bottom.active {
-                            faces.push(Face::new(Side::Bottom, voxel_pos_local, voxel.voxel_type));
-                        }
-                        if !back.active {
-                            faces.push(Face::new(Side::Back, voxel_pos_local, voxel.voxel_type));
-                        }
-                        if !right.active {
-                            faces.push(Face::new(Side::Right, voxel_pos_local, voxel.voxel_type));
+/// How the vertices of blocky chunk meshes are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkVertexFormat {
+    /// Position, normal, UV and tint as floats, drawn with a `StandardMaterial`
+    #[default]
+    Standard,
+    /// Two u32s per vertex, see `voxel_material::pack_vertex`, drawn with a `VoxelMaterial`.
+    /// Smooth meshes don't sit on the voxel grid, so they keep a format of their own
+    Packed,
+}
+
+/// The level of detail a chunk is meshed at, where each level halves the resolution.
+/// Sides bordering a chunk at another level get skirts, faces on every solid cell along
+/// the border, so the differences between the levels don't leave cracks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshLod {
+    pub level: u32,
+    pub skirts: [bool; 6],
+}
+
+/// Part of a chunk's mesh drawn with one material
+pub struct Submesh {
+    pub material: BlockMaterial,
+    pub mesh: Mesh,
+    /// How many of the first indices belong to solid blocks. The fluids' faces come after
+    /// them, and are left out of the collider
+    pub solid_indices: usize,
+}
+
+/// Meshes of the chunk's faces, one for each material they're drawn with. Faces are kept
+/// next to see-through blocks of another type, so what's behind them shows, and solid
+/// faces next to fluids, so the collider has a floor under them
+pub fn build_mesh(
+    chunk: &PaddedChunk,
+    lod: &MeshLod,
+    vertex_format: ChunkVertexFormat,
+    textures: &BlockTextures,
+    materials: &BlockMaterials,
+) -> Vec<Submesh> {
+    let mut faces = vec![Vec::<Face>::new(); materials.count()];
+    let mut fluid_faces = vec![Vec::<Face>::new(); materials.count()];
+    let cell_size = 1 << lod.level.min(MAX_LOD_LEVEL);
+    let cells = (CHUNK_SIZE / cell_size) as i32;
+
+    for x in 0..cells {
+        for y in 0..cells {
+            for z in 0..cells {
+                let cell_pos = IVec3::new(x, y, z);
+                let Some(voxel_type) = sample_cell(chunk, &cell_pos, cell_size) else { continue; };
+
+                let size = cell_size as f32;
+                let center =
+                    (cell_pos * cell_size as i32).as_vec3() + Vec3::splat((size - 1.0) / 2.0);
+                for (side_index, offset) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+                    let neighbour_pos = cell_pos + *offset;
+                    let outside = neighbour_pos.cmplt(IVec3::ZERO).any()
+                        || neighbour_pos.cmpge(IVec3::splat(cells)).any();
+                    let hidden =
+                        sample_cell(chunk, &neighbour_pos, cell_size).is_some_and(|neighbour| {
+                            neighbour == voxel_type
+                                || !(materials.is_see_through(neighbour)
+                                    || neighbour.is_fluid() && !voxel_type.is_fluid())
+                        });
+                    let visible = (outside && lod.skirts[side_index]) || !hidden;
+                    if visible {
+                        let side = Side::ALL[side_index];
+                        let mut face = Face::with_size(side, center, size, voxel_type);
+                        apply_texture_rule(chunk, textures, &mut face, cell_pos, cell_size);
+                        if let Some(tint) = voxel_type.tint(side) {
+                            face.tint = chunk.tint_color(tint, cell_pos * cell_size as i32);
                         }
-                        if !top.active {
-                            faces.push(Face::new(Side::Top, voxel_pos_local, voxel.voxel_type));
+                        if cell_size == 1 {
+                            face.occlusion = face_occlusion(chunk, materials, &face, cell_pos);
                         }
-                        if !front.active {
-                            faces.push(Face::new(Side::Front, voxel_pos_local, voxel.voxel_type));
+                        let material = materials.block_material(voxel_type).index();
+                        if voxel_type.is_fluid() {
+                            fluid_faces[material].push(face);
+                        } else {
+                            faces[material].push(face);
                         }
                     }
                 }
@@ -69,42 +148,461 @@ pub fn build_mesh(chunk_manager: &ChunkManager, chunk: &Chunk, chunk_pos: &IVec3
         }
     }
 
-    let mut vertices = Vec::<([f32; 3], [f32; 3], [f32; 2])>::new();
-    let mut indices = Vec::<u32>::new();
-    let mut vert_index = 0;
-
-    for face in faces {
-        (0..4).for_each(|index| {
-            vertices.push((
-                face.vertices[index].into(),
-                face.normal.into(),
-                face.uv[index].into(),
-            ));
+    materials
+        .ids()
+        .zip(faces.into_iter().zip(fluid_faces))
+        .filter(|(_, (faces, fluid_faces))| !faces.is_empty() || !fluid_faces.is_empty())
+        .map(|(material, (mut faces, fluid_faces))| {
+            let solid_indices = faces.len() * 6;
+            faces.extend(fluid_faces);
+            Submesh {
+                material,
+                mesh: faces_mesh(&faces, vertex_format),
+                solid_indices,
+            }
+        })
+        .collect()
+}
+
+fn faces_mesh(faces: &[Face], vertex_format: ChunkVertexFormat) -> Mesh {
+    let mut indices = Vec::<u32>::with_capacity(faces.len() * 6);
+    for (face_index, face) in faces.iter().enumerate() {
+        let vert_index = face_index as u32 * 4;
+        // Split the quad along the diagonal with the darker corners so the occlusion
+        // doesn't show up as a crease across the lighter one
+        let [a, b, c, d] = face.occlusion;
+        let turn = if a + c < b + d { 1 } else { 0 };
+        indices.extend([0, 1, 2, 0, 2, 3].map(|corner| vert_index + (corner + turn) % 4));
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    match vertex_format {
+        ChunkVertexFormat::Standard => {
+            let mut positions = Vec::<[f32; 3]>::with_capacity(faces.len() * 4);
+            let mut normals = Vec::<[f32; 3]>::with_capacity(faces.len() * 4);
+            let mut tangents = Vec::<[f32; 4]>::with_capacity(faces.len() * 4);
+            let mut uvs = Vec::<[f32; 2]>::with_capacity(faces.len() * 4);
+            let mut colors = Vec::<[f32; 4]>::with_capacity(faces.len() * 4);
+            for face in faces.iter() {
+                let tangent = face.tangent();
+                for index in 0..4 {
+                    positions.push(face.vertices[index].into());
+                    normals.push(face.normal.into());
+                    tangents.push(tangent.into());
+                    uvs.push(face.uv[index].into());
+                    let brightness = occlusion_brightness(face.occlusion[index]);
+                    colors.push((face.tint * brightness).extend(1.0).into());
+                }
+            }
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, tangents);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        }
+        ChunkVertexFormat::Packed => {
+            let packed: Vec<[u32; 2]> = faces
+                .iter()
+                .flat_map(|face| (0..4).map(|corner| pack_vertex(face, corner)))
+                .collect();
+            mesh.insert_attribute(ATTRIBUTE_PACKED_VOXEL, packed);
+        }
+    }
+    mesh
+}
+
+/// Ambient occlusion at each vertex of a face of a single voxel, counting the opaque blocks
+/// on the two sides and across the corner in front of it. Both sides shut the corner in
+/// whatever is across it
+fn face_occlusion(
+    chunk: &PaddedChunk,
+    materials: &BlockMaterials,
+    face: &Face,
+    voxel_pos: IVec3,
+) -> [u32; 4] {
+    let center = voxel_pos.as_vec3();
+    let normal = face.normal.as_ivec3();
+    let occludes = |pos: IVec3| {
+        let voxel = chunk.get(pos);
+        voxel.is_solid() && !materials.is_see_through(voxel.voxel_type)
+    };
+    std::array::from_fn(|corner| {
+        // Steps along the face towards the corner, one per axis it lies in
+        let towards =
+            (face.vertices[corner] - center).signum().as_ivec3() * (IVec3::ONE - normal.abs());
+        let mut steps = [IVec3::X, IVec3::Y, IVec3::Z]
+            .into_iter()
+            .map(|axis| axis * towards)
+            .filter(|step| *step != IVec3::ZERO);
+        let (Some(first), Some(second)) = (steps.next(), steps.next()) else { return NO_OCCLUSION; };
+        let front = voxel_pos + normal;
+        let first = occludes(front + first);
+        let second = occludes(front + second);
+        if first && second {
+            return 0;
+        }
+        NO_OCCLUSION - first as u32 - second as u32 - occludes(front + towards) as u32
+    })
+}
+
+/// Pick the sprite of the face on the cell by the rule of its block side. Cells of
+/// several voxels don't join up, so connected textures keep their plain sprite
+fn apply_texture_rule(
+    chunk: &PaddedChunk,
+    textures: &BlockTextures,
+    face: &mut Face,
+    cell_pos: IVec3,
+    cell_size: usize,
+) {
+    let voxel_pos = cell_pos * cell_size as i32;
+    face.set_sprite(textures.sprite(face.voxel_type, face.side), 0);
+    match textures.rule(face.voxel_type, face.side) {
+        TextureRule::Single => {}
+        TextureRule::Random {
+            strip,
+            count,
+            rotate,
+        } => {
+            let hash = block_side_hash(chunk.origin() + voxel_pos, face.side);
+            let variant = (hash % (count as u64 + 1)) as u32;
+            let sprite = match variant {
+                0 => face.sprite,
+                _ => strip + UVec2::X * (variant - 1),
+            };
+            let rotation = if rotate { (hash >> 32) as usize % 4 } else { 0 };
+            face.set_sprite(sprite, rotation);
+        }
+        TextureRule::Connected { strip } if cell_size == 1 => {
+            face.set_sprite(strip + UVec2::X * connected_mask(chunk, face, voxel_pos), 0);
+        }
+        TextureRule::Connected { .. } => {}
+    }
+}
+
+/// Hash of a block side, the same in whichever chunk the block is meshed. Like the
+/// structures' cell seeds, positions within reach of an i32 only mix in their low half
+fn block_side_hash(world_pos: I64Vec3, side: Side) -> u64 {
+    let mut hash = side as u64;
+    for value in world_pos.to_array() {
+        let high = i32::try_from(value)
+            .is_err()
+            .then_some((value >> 32) as u32 as u64);
+        for half in [Some(value as u32 as u64), high].into_iter().flatten() {
+            hash = (hash ^ half).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            hash ^= hash >> 29;
+        }
+    }
+    hash
+}
+
+/// Bits of the neighbours along the face that join it, up, right, down and left as seen on
+/// the sprite. Neighbours join when they are of the same type and show the same side
+fn connected_mask(chunk: &PaddedChunk, face: &Face, voxel_pos: IVec3) -> u32 {
+    let right = (face.vertices[0] - face.vertices[1]).round().as_ivec3();
+    let down = (face.vertices[2] - face.vertices[1]).round().as_ivec3();
+    let normal = face.normal.as_ivec3();
+    [-down, right, down, -right]
+        .iter()
+        .enumerate()
+        .fold(0, |mask, (bit, direction)| {
+            let neighbour_pos = voxel_pos + *direction;
+            let joins = sample_cell(chunk, &neighbour_pos, 1) == Some(face.voxel_type)
+                && sample_cell(chunk, &(neighbour_pos + normal), 1).is_none();
+            if joins {
+                mask | 1 << bit
+            } else {
+                mask
+            }
+        })
+}
+
+/// The voxel type filling the cell of `cell_size` voxels per side, or None if it's mostly empty.
+/// Cells reaching into a neighbouring chunk only see its border layer, so they only count as
+/// solid if all of it is, which rather leaves a face too many than a hole in the border
+fn sample_cell(chunk: &PaddedChunk, cell_pos: &IVec3, cell_size: usize) -> Option<VoxelType> {
+    let inside = cell_pos.cmpge(IVec3::ZERO).all()
+        && cell_pos
+            .cmplt(IVec3::splat((CHUNK_SIZE / cell_size) as i32))
+            .all();
+    let get_voxel = |voxel_pos: IVec3| {
+        chunk.get(voxel_pos.clamp(IVec3::NEG_ONE, IVec3::splat(CHUNK_SIZE as i32)))
+    };
+
+    let first_voxel = *cell_pos * cell_size as i32;
+    if cell_size == 1 {
+        let voxel = get_voxel(first_voxel);
+        return voxel.active.then_some(voxel.voxel_type);
+    }
+
+    // Count the voxel types, and keep the most common one if at least half the cell is solid
+    let mut counts = Vec::<(VoxelType, usize)>::new();
+    let mut active = 0;
+    for x in 0..cell_size as i32 {
+        for y in 0..cell_size as i32 {
+            for z in 0..cell_size as i32 {
+                let voxel = get_voxel(first_voxel + IVec3::new(x, y, z));
+                if !voxel.active {
+                    continue;
+                }
+                active += 1;
+                match counts
+                    .iter_mut()
+                    .find(|(voxel_type, _)| *voxel_type == voxel.voxel_type)
+                {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((voxel.voxel_type, 1)),
+                }
+            }
+        }
+    }
+    let cell_volume = cell_size * cell_size * cell_size;
+    if (inside && active * 2 < cell_volume) || (!inside && active < cell_volume) {
+        return None;
+    }
+    counts
+        .iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(voxel_type, _)| *voxel_type)
+}
+
+/// Smooth mesh of the chunk, extracted from the voxel densities with Surface Nets.
+/// Every cell between eight voxel centers that the surface passes through gets a vertex at
+/// the average of the crossings on its edges, and every meshed voxel next to an empty one a
+/// quad between the four cells around the voxel edge. Vertices only depend on the voxels
+/// around them, so the cells shared with a neighbour come out the same in both chunks and
+/// the meshes meet without seams. Normals follow the density gradient, and positions stay
+/// in voxel units, so `VoxelMaterial` can texture the mesh by triplanar projection with the
+/// sprites of the cell's most common block, held in `ATTRIBUTE_SMOOTH_SPRITES`. The normal map
+/// is projected too, in the frame of each axis, so the mesh needs no tangents. The colours
+/// hold its tint. The ground is drawn with the opaque material, as cells share vertices
+/// whatever their blocks. Fluids count as empty for it, and get a surface of their own in the
+/// submesh of their material, with a level top where they meet the air
+pub fn build_smooth_mesh(
+    chunk: &PaddedChunk,
+    textures: &BlockTextures,
+    materials: &BlockMaterials,
+) -> Vec<Submesh> {
+    let mut submeshes = Vec::new();
+    let ground = smooth_surface(chunk, textures, Voxel::is_solid, Voxel::is_solid, |voxel| {
+        let density = voxel.density as f32 / FULL_DENSITY as f32;
+        if voxel.is_solid() {
+            density.max(ISO_LEVEL)
+        } else {
+            density.min(ISO_LEVEL)
+        }
+    });
+    if let Some((mesh, indices_len)) = ground {
+        submeshes.push(Submesh {
+            material: BlockMaterial::OPAQUE,
+            solid_indices: indices_len,
+            mesh,
         });
+    }
 
-        indices.push(vert_index);
-        indices.push(vert_index + 1);
-        indices.push(vert_index + 2);
-        indices.push(vert_index);
-        indices.push(vert_index + 2);
-        indices.push(vert_index + 3);
-        vert_index += 4;
+    // Fluids are held in by the ground, so only their sides facing empty voxels are meshed.
+    // They're all the way full, so the top lies between voxels
+    for material in materials.ids() {
+        let is_fluid = |voxel: &Voxel| {
+            voxel.active
+                && voxel.voxel_type.is_fluid()
+                && materials.block_material(voxel.voxel_type) == material
+        };
+        let fluid = smooth_surface(
+            chunk,
+            textures,
+            |voxel| voxel.active,
+            is_fluid,
+            |voxel| if voxel.active { 1.0 } else { 0.0 },
+        );
+        if let Some((mesh, _)) = fluid {
+            submeshes.push(Submesh {
+                material,
+                solid_indices: 0,
+                mesh,
+            });
+        }
     }
+    submeshes
+}
+
+/// Surface Nets over the voxels `inside` counts as filled, with the densities given by
+/// `density`, and a quad on every side of a voxel `meshed` picks that faces one not inside.
+/// None if there are no quads, otherwise the mesh and its number of indices
+fn smooth_surface(
+    chunk: &PaddedChunk,
+    textures: &BlockTextures,
+    inside: impl Fn(&Voxel) -> bool,
+    meshed: impl Fn(&Voxel) -> bool,
+    density: impl Fn(&Voxel) -> f32,
+) -> Option<(Mesh, usize)> {
+    let chunk_size = CHUNK_SIZE as i32;
+    let voxel = |pos: IVec3| chunk.get(pos);
+
+    let mut positions = Vec::<[f32; 3]>::new();
+    let mut normals = Vec::<[f32; 3]>::new();
+    let mut sprites = Vec::<[u32; 2]>::new();
+    let mut colors = Vec::<[f32; 4]>::new();
+    let mut cell_vertices = vec![None; SMOOTH_CELLS * SMOOTH_CELLS * SMOOTH_CELLS];
+    let mut cell_vertex = |cell_pos: IVec3| -> Option<u32> {
+        let cell = (cell_pos + IVec3::ONE).as_uvec3();
+        let index =
+            (cell.x as usize * SMOOTH_CELLS + cell.y as usize) * SMOOTH_CELLS + cell.z as usize;
+        if cell_vertices[index].is_none() {
+            let corners: [&Voxel; 8] =
+                std::array::from_fn(|corner| voxel(cell_pos + corner_offset(corner)));
+            let (position, normal, voxel_type) =
+                smooth_vertex(&corners, &inside, &meshed, &density)?;
+            let sprite = |side: Side| textures.sprite(voxel_type, side);
+            let side = dominant_side(normal);
+            let tint = voxel_type
+                .tint(side)
+                .map_or(Vec3::ONE, |tint| chunk.tint_color(tint, cell_pos));
+            cell_vertices[index] = Some(positions.len() as u32);
+            positions.push((cell_pos.as_vec3() + position).into());
+            normals.push(normal.into());
+            sprites.push(pack_smooth_sprites(
+                sprite(Side::Top),
+                sprite(Side::Bottom),
+                sprite(Side::Front),
+            ));
+            colors.push(tint.extend(1.0).into());
+        }
+        cell_vertices[index]
+    };
 
-    let mesh_indices = Indices::U32(indices);
-    let mut positions = Vec::new();
-    let mut normals = Vec::new();
-    let mut uvs = Vec::new();
-    for (position, normal, uv) in vertices.iter() {
-        positions.push(*position);
-        normals.push(*normal);
-        uvs.push(*uv);
+    let mut indices = Vec::<u32>::new();
+    for x in 0..chunk_size {
+        for y in 0..chunk_size {
+            for z in 0..chunk_size {
+                let voxel_pos = IVec3::new(x, y, z);
+                if !meshed(voxel(voxel_pos)) {
+                    continue;
+                }
+                for axis in 0..3 {
+                    let forward = IVec3::AXES[axis];
+                    let (side_a, side_b) =
+                        (IVec3::AXES[(axis + 1) % 3], IVec3::AXES[(axis + 2) % 3]);
+                    for (direction, edge_pos) in [(1, voxel_pos), (-1, voxel_pos - forward)] {
+                        if inside(voxel(voxel_pos + forward * direction)) {
+                            continue;
+                        }
+                        // The cells around the edge, going counter-clockwise around `forward`
+                        let quad = [
+                            cell_vertex(edge_pos),
+                            cell_vertex(edge_pos - side_a),
+                            cell_vertex(edge_pos - side_a - side_b),
+                            cell_vertex(edge_pos - side_b),
+                        ];
+                        let [Some(a), Some(b), Some(c), Some(d)] = quad else { continue; };
+                        if direction > 0 {
+                            indices.extend([a, b, c, a, c, d]);
+                        } else {
+                            indices.extend([a, c, b, a, d, c]);
+                        }
+                    }
+                }
+            }
+        }
     }
 
+    if indices.is_empty() {
+        return None;
+    }
+    let indices_len = indices.len();
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    mesh.set_indices(Some(mesh_indices));
+    mesh.set_indices(Some(Indices::U32(indices)));
     mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
     mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    mesh
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_attribute(ATTRIBUTE_SMOOTH_SPRITES, sprites);
+    Some((mesh, indices_len))
+}
+
+/// Offset of a cell corner from the cell, with the corner given as bits of x, y and z
+fn corner_offset(corner: usize) -> IVec3 {
+    IVec3::new(
+        (corner >> 2) as i32,
+        (corner >> 1 & 1) as i32,
+        (corner & 1) as i32,
+    )
+}
+
+/// Position within the cell, normal and block type of the vertex of the cell with the given
+/// corners, or None if the surface doesn't pass through it. The block is the most common
+/// one among the meshed corners
+fn smooth_vertex(
+    corners: &[&Voxel; 8],
+    inside: impl Fn(&Voxel) -> bool,
+    meshed: impl Fn(&Voxel) -> bool,
+    density: impl Fn(&Voxel) -> f32,
+) -> Option<(Vec3, Vec3, VoxelType)> {
+    let corner_pos = |corner: usize| corner_offset(corner).as_vec3();
+    let filled = corners.map(&inside);
+    let densities = corners.map(density);
+
+    let mut crossing_sum = Vec3::ZERO;
+    let mut crossings = 0;
+    for (from, to) in CELL_EDGES {
+        if filled[from] == filled[to] {
+            continue;
+        }
+        let difference = densities[to] - densities[from];
+        let t = if difference == 0.0 {
+            0.5
+        } else {
+            ((ISO_LEVEL - densities[from]) / difference).clamp(0.0, 1.0)
+        };
+        crossing_sum += corner_pos(from).lerp(corner_pos(to), t);
+        crossings += 1;
+    }
+    if crossings == 0 {
+        return None;
+    }
+
+    // The density grows into the ground, so the surface faces the other way
+    let mut gradient = Vec3::ZERO;
+    for (corner, density) in densities.iter().enumerate() {
+        gradient += (corner_pos(corner) * 2.0 - Vec3::ONE) * *density;
+    }
+    let normal = (-gradient).try_normalize().unwrap_or(Vec3::Y);
+
+    let mut counts = Vec::<(VoxelType, usize)>::new();
+    for corner in corners.iter().filter(|corner| meshed(corner)) {
+        match counts
+            .iter_mut()
+            .find(|(voxel_type, _)| *voxel_type == corner.voxel_type)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((corner.voxel_type, 1)),
+        }
+    }
+    let voxel_type = counts
+        .iter()
+        .max_by_key(|(_, count)| *count)
+        .map_or(VoxelType::Default, |(voxel_type, _)| *voxel_type);
+
+    Some((crossing_sum / crossings as f32, normal, voxel_type))
+}
+
+/// The cube side facing closest to the normal, to pick sprites like the side textures of grass
+fn dominant_side(normal: Vec3) -> Side {
+    let abs = normal.abs();
+    if abs.y >= abs.x && abs.y >= abs.z {
+        if normal.y > 0.0 {
+            Side::Top
+        } else {
+            Side::Bottom
+        }
+    } else if abs.x >= abs.z {
+        if normal.x > 0.0 {
+            Side::Right
+        } else {
+            Side::Left
+        }
+    } else if normal.z > 0.0 {
+        Side::Front
+    } else {
+        Side::Back
+    }
 }
@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{chunk::CHUNK_SIZE, chunk_manager::ChunkManager, render_settings::RenderSettings};
+
+/// How far below its place a chunk starts sliding in from, in voxels
+pub const SLIDE_IN_DEPTH: f32 = CHUNK_SIZE as f32 / 2.0;
+/// How long a chunk takes to slide into place, in seconds
+pub const SLIDE_IN_DURATION: f32 = 0.5;
+/// Where the distance fog starts, as a fraction of where it ends
+pub const FOG_START: f32 = 0.5;
+
+/// Hides chunks popping in at the edge of the render distance, by sliding new chunks into
+/// place and fogging the loading frontier, as set in the `RenderSettings`
+pub struct ChunkTransitionPlugin;
+
+impl Plugin for ChunkTransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems((slide_in_chunks, apply_distance_fog));
+    }
+}
+
+/// How chunks appear once their meshes are built
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkTransition {
+    /// All at once
+    #[default]
+    Instant,
+    /// Rising into place from `SLIDE_IN_DEPTH` below, over `SLIDE_IN_DURATION`. Only the
+    /// drawn submeshes move, the collider is in place from the start
+    SlideIn,
+}
+
+/// Submesh of a newly spawned chunk that's still sliding into place
+#[derive(Component, Default)]
+pub struct SlidingIn {
+    elapsed: f32,
+}
+
+impl SlidingIn {
+    /// Where the sliding submesh starts out, relative to its chunk
+    pub fn start_transform() -> Transform {
+        Transform::from_translation(Vec3::NEG_Y * SLIDE_IN_DEPTH)
+    }
+}
+
+fn slide_in_chunks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut sliding: Query<(Entity, &mut Transform, &mut SlidingIn)>,
+) {
+    for (entity, mut transform, mut sliding_in) in sliding.iter_mut() {
+        sliding_in.elapsed += time.delta_seconds();
+        let progress = (sliding_in.elapsed / SLIDE_IN_DURATION).min(1.0);
+        // Eased out, so chunks settle gently
+        let remaining = (1.0 - progress).powi(3);
+        transform.translation = Vec3::NEG_Y * SLIDE_IN_DEPTH * remaining;
+        if progress >= 1.0 {
+            commands.entity(entity).remove::<SlidingIn>();
+        }
+    }
+}
+
+/// Fog closing in just inside the render distance, in the clear colour, so chunks that are
+/// still loading beyond it can't be seen
+fn apply_distance_fog(
+    mut commands: Commands,
+    settings: Res<RenderSettings>,
+    chunk_manager: Res<ChunkManager>,
+    clear_color: Res<ClearColor>,
+    mut cameras: Query<(Entity, Option<&mut FogSettings>), With<Camera3d>>,
+) {
+    // The outermost loaded chunks wait on their unloaded neighbours to be meshed
+    let end = (chunk_manager.render_distance() - 1) as f32 * CHUNK_SIZE as f32;
+    let start = end * FOG_START;
+    for (camera, camera_fog) in cameras.iter_mut() {
+        match (settings.distance_fog, camera_fog) {
+            (true, Some(mut camera_fog)) => {
+                // Only written when it changes, so change detection stays quiet
+                let unchanged = match camera_fog.falloff {
+                    FogFalloff::Linear {
+                        start: fog_start,
+                        end: fog_end,
+                    } => camera_fog.color == clear_color.0 && fog_start == start && fog_end == end,
+                    _ => false,
+                };
+                if !unchanged {
+                    *camera_fog = distance_fog(clear_color.0, start, end);
+                }
+            }
+            (true, None) => {
+                commands
+                    .entity(camera)
+                    .insert(distance_fog(clear_color.0, start, end));
+            }
+            (false, Some(_)) => {
+                commands.entity(camera).remove::<FogSettings>();
+            }
+            (false, None) => {}
+        }
+    }
+}
+
+fn distance_fog(color: Color, start: f32, end: f32) -> FogSettings {
+    FogSettings {
+        color,
+        falloff: FogFalloff::Linear { start, end },
+        ..default()
+    }
+}
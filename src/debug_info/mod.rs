@@ -12,7 +12,7 @@ pub struct DebugInfoPlugin;
 impl Plugin for DebugInfoPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(EguiPlugin)
-            .add_plugin(FrameTimeDiagnosticsPlugin::default())
+            .add_plugin(FrameTimeDiagnosticsPlugin)
             .add_system(display_debug_info)
             .add_system(update)
             .init_resource::<DebugInfoState>();
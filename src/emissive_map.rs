@@ -0,0 +1,123 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{
+    chunk_manager::ChunkManager,
+    voxel_textures::{
+        emissive_sprites, sprite_cell_rows, BlockTextures, PIXEL_SIZE, SPRITE_CELL, TEXTURE_HEIGHT,
+        TEXTURE_WIDTH,
+    },
+};
+
+/// Keeps the emissive map of the chunk materials in step with the spritesheet, animations
+/// included
+pub struct EmissiveMapPlugin;
+
+impl Plugin for EmissiveMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(update_emissive_map);
+    }
+}
+
+/// Black image the size of the spritesheet, for the emissive map to be drawn into once the
+/// spritesheet is loaded
+pub fn empty_emissive_map() -> Image {
+    Image::new_fill(
+        Extent3d {
+            width: TEXTURE_WIDTH as u32,
+            height: TEXTURE_HEIGHT as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Single black pixel, the emissive map of materials that don't glow
+pub fn no_emission_map() -> Image {
+    Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Spritesheet and block textures the emissive map was last drawn from
+struct DrawnFrom {
+    spritesheet_handle: Handle<Image>,
+    block_textures: BlockTextures,
+}
+
+fn update_emissive_map(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    chunk_manager: Res<ChunkManager>,
+    mut images: ResMut<Assets<Image>>,
+    mut drawn_from: Local<Option<DrawnFrom>>,
+) {
+    let spritesheet_changed = image_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+            *handle == chunk_manager.spritesheet_handle
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+    // Resource packs can switch to a spritesheet that's already loaded
+    let packs_changed = !drawn_from.as_ref().is_some_and(|drawn_from| {
+        drawn_from.spritesheet_handle == chunk_manager.spritesheet_handle
+            && drawn_from.block_textures == *chunk_manager.block_textures()
+    });
+    if !spritesheet_changed && !packs_changed {
+        return;
+    }
+    let Some(spritesheet) = images.get(&chunk_manager.spritesheet_handle) else { return; };
+    let sprite_pixels: Vec<_> = emissive_sprites(chunk_manager.block_textures())
+        .into_iter()
+        .filter_map(|(sprite, color)| {
+            Some((sprite, color, sprite_cell(&spritesheet.data, sprite)?))
+        })
+        .collect();
+    let Some(emissive_map) = images.get_mut(&chunk_manager.emissive_map_handle) else { return; };
+
+    // Only the emissive sprites are drawn, the rest of the map is black
+    for pixel in emissive_map.data.chunks_exact_mut(PIXEL_SIZE) {
+        pixel.copy_from_slice(&[0, 0, 0, u8::MAX]);
+    }
+    for (sprite, color, pixels) in sprite_pixels {
+        let glowing = pixels.chunks_exact(PIXEL_SIZE).flat_map(|pixel| {
+            let rgb = Vec3::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32) * color;
+            [rgb.x as u8, rgb.y as u8, rgb.z as u8, u8::MAX]
+        });
+        write_sprite_cell(&mut emissive_map.data, sprite, glowing.collect());
+    }
+    *drawn_from = Some(DrawnFrom {
+        spritesheet_handle: chunk_manager.spritesheet_handle.clone(),
+        block_textures: chunk_manager.block_textures().clone(),
+    });
+}
+
+/// Pixels of the sprite, padding included, row by row. None if the image is too small to
+/// hold it
+fn sprite_cell(data: &[u8], sprite: UVec2) -> Option<Vec<u8>> {
+    let mut pixels = Vec::with_capacity(SPRITE_CELL * SPRITE_CELL * PIXEL_SIZE);
+    for row in sprite_cell_rows(sprite) {
+        pixels.extend_from_slice(data.get(row)?);
+    }
+    Some(pixels)
+}
+
+/// Write the pixels over the sprite, leaving out the rows the image is too small for
+fn write_sprite_cell(data: &mut [u8], sprite: UVec2, pixels: Vec<u8>) {
+    let cell_size = SPRITE_CELL * PIXEL_SIZE;
+    for (row, pixels) in sprite_cell_rows(sprite).zip(pixels.chunks_exact(cell_size)) {
+        if let Some(row) = data.get_mut(row) {
+            row.copy_from_slice(pixels);
+        }
+    }
+}
@@ -1,6 +1,6 @@
-use bevy::prelude::{Vec2, Vec3};
+use bevy::prelude::{UVec2, Vec2, Vec3, Vec4};
 
-use crate::{voxel::VoxelType, voxel_textures::*};
+use crate::{voxel::VoxelType, voxel_material::NO_OCCLUSION, voxel_textures::*};
 
 pub const HALF_SIZE: f32 = 0.5;
 pub const UVS: [Vec2; 4] = [
@@ -20,6 +20,29 @@ pub enum Side {
     Back,
 }
 
+impl Side {
+    /// In the order of `chunk_mesh_builder::NEIGHBOUR_OFFSETS`
+    pub const ALL: [Side; 6] = [
+        Side::Right,
+        Side::Left,
+        Side::Top,
+        Side::Bottom,
+        Side::Front,
+        Side::Back,
+    ];
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Right => Side::Left,
+            Side::Left => Side::Right,
+            Side::Top => Side::Bottom,
+            Side::Bottom => Side::Top,
+            Side::Front => Side::Back,
+            Side::Back => Side::Front,
+        }
+    }
+}
+
 pub fn get_normal(side: Side) -> Vec3 {
     match side {
         Side::Right => Vec3::X,
@@ -37,54 +60,95 @@ pub struct Face {
     pub normal: Vec3,
     pub vertices: [Vec3; 4],
     pub side: Side,
+    pub voxel_type: VoxelType,
+    /// Column and row of the sprite in the spritesheet
+    pub sprite: UVec2,
+    /// Quarter turns the sprite is rotated by
+    pub uv_rotation: usize,
+    /// Colour the texture is multiplied with, white unless the block is tinted
+    pub tint: Vec3,
+    /// Ambient occlusion at each vertex, from 0 when boxed in to `NO_OCCLUSION`
+    pub occlusion: [u32; 4],
 }
 
 impl Face {
     pub fn new(side: Side, pos: Vec3, voxel_type: VoxelType) -> Self {
+        Face::with_size(side, pos, 1.0, voxel_type)
+    }
+
+    /// Face of a cube `size` voxels wide centered on pos, used for downsampled meshes
+    pub fn with_size(side: Side, pos: Vec3, size: f32, voxel_type: VoxelType) -> Self {
+        let half_size = HALF_SIZE * size;
         let vertices = match side {
             Side::Left => [
-                Vec3::new(pos.x - HALF_SIZE, pos.y + HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y + HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y - HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y - HALF_SIZE, pos.z + HALF_SIZE),
+                Vec3::new(pos.x - half_size, pos.y + half_size, pos.z + half_size),
+                Vec3::new(pos.x - half_size, pos.y + half_size, pos.z - half_size),
+                Vec3::new(pos.x - half_size, pos.y - half_size, pos.z - half_size),
+                Vec3::new(pos.x - half_size, pos.y - half_size, pos.z + half_size),
             ],
             Side::Right => [
-                Vec3::new(pos.x + HALF_SIZE, pos.y + HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y + HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y - HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y - HALF_SIZE, pos.z - HALF_SIZE),
+                Vec3::new(pos.x + half_size, pos.y + half_size, pos.z - half_size),
+                Vec3::new(pos.x + half_size, pos.y + half_size, pos.z + half_size),
+                Vec3::new(pos.x + half_size, pos.y - half_size, pos.z + half_size),
+                Vec3::new(pos.x + half_size, pos.y - half_size, pos.z - half_size),
             ],
             Side::Top => [
-                Vec3::new(pos.x + HALF_SIZE, pos.y + HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y + HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y + HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y + HALF_SIZE, pos.z + HALF_SIZE),
+                Vec3::new(pos.x + half_size, pos.y + half_size, pos.z - half_size),
+                Vec3::new(pos.x - half_size, pos.y + half_size, pos.z - half_size),
+                Vec3::new(pos.x - half_size, pos.y + half_size, pos.z + half_size),
+                Vec3::new(pos.x + half_size, pos.y + half_size, pos.z + half_size),
             ],
             Side::Bottom => [
-                Vec3::new(pos.x - HALF_SIZE, pos.y - HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y - HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y - HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y - HALF_SIZE, pos.z + HALF_SIZE),
+                Vec3::new(pos.x - half_size, pos.y - half_size, pos.z - half_size),
+                Vec3::new(pos.x + half_size, pos.y - half_size, pos.z - half_size),
+                Vec3::new(pos.x + half_size, pos.y - half_size, pos.z + half_size),
+                Vec3::new(pos.x - half_size, pos.y - half_size, pos.z + half_size),
             ],
             Side::Back => [
-                Vec3::new(pos.x - HALF_SIZE, pos.y + HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y + HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y - HALF_SIZE, pos.z - HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y - HALF_SIZE, pos.z - HALF_SIZE),
+                Vec3::new(pos.x - half_size, pos.y + half_size, pos.z - half_size),
+                Vec3::new(pos.x + half_size, pos.y + half_size, pos.z - half_size),
+                Vec3::new(pos.x + half_size, pos.y - half_size, pos.z - half_size),
+                Vec3::new(pos.x - half_size, pos.y - half_size, pos.z - half_size),
             ],
             Side::Front => [
-                Vec3::new(pos.x + HALF_SIZE, pos.y + HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y + HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x - HALF_SIZE, pos.y - HALF_SIZE, pos.z + HALF_SIZE),
-                Vec3::new(pos.x + HALF_SIZE, pos.y - HALF_SIZE, pos.z + HALF_SIZE),
+                Vec3::new(pos.x + half_size, pos.y + half_size, pos.z + half_size),
+                Vec3::new(pos.x - half_size, pos.y + half_size, pos.z + half_size),
+                Vec3::new(pos.x - half_size, pos.y - half_size, pos.z + half_size),
+                Vec3::new(pos.x + half_size, pos.y - half_size, pos.z + half_size),
             ],
         };
 
+        let sprite = get_voxel_type_sprite(voxel_type, side);
         Self {
-            uv: get_voxel_type_uv(voxel_type, side),
+            uv: get_sprite_uv(sprite),
             normal: get_normal(side),
-            vertices: vertices,
-            side: side,
+            vertices,
+            side,
+            voxel_type,
+            sprite,
+            uv_rotation: 0,
+            tint: Vec3::ONE,
+            occlusion: [NO_OCCLUSION; 4],
         }
     }
+
+    /// Show another sprite, turned by `uv_rotation` quarter turns
+    pub fn set_sprite(&mut self, sprite: UVec2, uv_rotation: usize) {
+        let uv = get_sprite_uv(sprite);
+        self.sprite = sprite;
+        self.uv_rotation = uv_rotation % 4;
+        self.uv = std::array::from_fn(|corner| uv[(corner + self.uv_rotation) % 4]);
+    }
+
+    /// Direction the sprite's U runs along the face, with the handedness of its V in w like
+    /// `Mesh::ATTRIBUTE_TANGENT`, so normal maps follow the sprite as it's turned
+    pub fn tangent(&self) -> Vec4 {
+        let edges = [self.vertices[1], self.vertices[2]].map(|vertex| vertex - self.vertices[0]);
+        let uv_edges = [self.uv[1], self.uv[2]].map(|uv| uv - self.uv[0]);
+        let determinant = uv_edges[0].perp_dot(uv_edges[1]);
+        let tangent = (edges[0] * uv_edges[1].y - edges[1] * uv_edges[0].y) / determinant;
+        let bitangent = (edges[1] * uv_edges[0].x - edges[0] * uv_edges[1].x) / determinant;
+        let handedness = self.normal.cross(tangent).dot(bitangent).signum();
+        tangent.normalize().extend(handedness)
+    }
 }
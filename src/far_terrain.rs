@@ -0,0 +1,328 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    math::Vec3Swizzles,
+    pbr::NotShadowCaster,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::Indices,
+        render_resource::{AsBindGroup, PrimitiveTopology, ShaderRef},
+    },
+    tasks::AsyncComputeTaskPool,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    chunk::CHUNK_SIZE,
+    chunk_manager::ChunkManager,
+    world_coords::{I64Vec2, I64Vec3},
+    world_gen_settings::WorldGenSettings,
+    world_generator::{WorldGenerator, SURFACE_SEARCH_DEPTH},
+};
+
+/// Width of a far terrain tile in voxels
+pub const FAR_TILE_SIZE: i32 = CHUNK_SIZE as i32 * 4;
+/// Distance between the columns sampled for the far terrain
+pub const FAR_SAMPLE_SPACING: i32 = 4;
+/// How far out the far terrain reaches, in tiles from the camera. Kept within the camera's
+/// far plane
+pub const FAR_TERRAIN_DISTANCE: i32 = 14;
+/// The far terrain sits this much below the generated surface, so real chunks cover it where
+/// both are shown
+pub const FAR_TERRAIN_SINK: f32 = 3.0;
+/// Width of the band along the edge of the loaded chunks over which the far terrain is
+/// dithered in, so it doesn't start at a hard line
+pub const FAR_TERRAIN_FADE: f32 = CHUNK_SIZE as f32;
+pub const FAR_TERRAIN_SHADER_PATH: &str = "shaders/far_terrain.wgsl";
+pub const MAX_FAR_TILE_TASKS: usize = 8;
+
+const SAMPLES_PER_SIDE: usize = (FAR_TILE_SIZE / FAR_SAMPLE_SPACING) as usize + 1;
+
+/// Coarse heightmap of the world beyond the loaded chunks, sampled from the world generator
+/// without structures or edits
+pub struct FarTerrainPlugin;
+
+impl Plugin for FarTerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FarTerrain>()
+            .add_startup_system(setup_far_terrain)
+            .add_systems((
+                reset_far_terrain,
+                queue_far_tiles.after(reset_far_terrain),
+                spawn_far_tiles.after(queue_far_tiles),
+                hide_loaded_far_tiles.after(spawn_far_tiles),
+                update_far_terrain_fade,
+            ));
+    }
+}
+
+#[derive(Component)]
+pub struct FarTile;
+
+#[derive(Resource, Default)]
+pub struct FarTerrain {
+    tiles: HashMap<I64Vec2, Entity>,
+    /// Tiles with a mesh being built
+    pending: HashSet<I64Vec2>,
+    /// Meshes built by the tasks, with the generation they were built for
+    built: Arc<Mutex<Vec<(u32, I64Vec2, Mesh)>>>,
+    /// Counts up on every change of the world gen settings, so meshes of the old world are dropped
+    generation: u32,
+    material_handle: Handle<FarTerrainMaterial>,
+}
+
+/// Material of the far tiles, coloured through their vertices and lit like a rough
+/// `StandardMaterial` by `FAR_TERRAIN_SHADER_PATH`
+#[derive(AsBindGroup, TypeUuid, Clone, Debug, Default)]
+#[uuid = "a3e61d52-7c08-4b9f-8d15-62c9f0b4e7a1"]
+pub struct FarTerrainMaterial {
+    /// Centre of the loaded chunks on x and z in the render space, and the distances from it
+    /// at which the tiles start and finish fading in
+    #[uniform(0)]
+    pub fade: Vec4,
+}
+
+impl Material for FarTerrainMaterial {
+    fn fragment_shader() -> ShaderRef {
+        FAR_TERRAIN_SHADER_PATH.into()
+    }
+}
+
+fn setup_far_terrain(
+    mut far_terrain: ResMut<FarTerrain>,
+    mut materials: ResMut<Assets<FarTerrainMaterial>>,
+) {
+    far_terrain.material_handle = materials.add(FarTerrainMaterial::default());
+}
+
+fn reset_far_terrain(
+    mut commands: Commands,
+    settings: Res<WorldGenSettings>,
+    mut far_terrain: ResMut<FarTerrain>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (_, entity) in far_terrain.tiles.drain() {
+        commands.entity(entity).despawn();
+    }
+    far_terrain.pending.clear();
+    far_terrain.generation = far_terrain.generation.wrapping_add(1);
+}
+
+/// Start building the missing tiles around the camera, closest first, and drop the ones
+/// that fell out of range
+fn queue_far_tiles(
+    mut commands: Commands,
+    settings: Res<WorldGenSettings>,
+    chunk_manager: Res<ChunkManager>,
+    mut far_terrain: ResMut<FarTerrain>,
+) {
+    let camera_tile = camera_tile(&chunk_manager);
+    far_terrain.tiles.retain(|tile, entity| {
+        let in_range =
+            (*tile - camera_tile).as_ivec2().abs().max_element() <= FAR_TERRAIN_DISTANCE + 1;
+        if !in_range {
+            commands.entity(*entity).despawn();
+        }
+        in_range
+    });
+
+    let free_tasks = MAX_FAR_TILE_TASKS.saturating_sub(far_terrain.pending.len());
+    if free_tasks == 0 {
+        return;
+    }
+    let mut missing = Vec::new();
+    for x in -FAR_TERRAIN_DISTANCE..=FAR_TERRAIN_DISTANCE {
+        for z in -FAR_TERRAIN_DISTANCE..=FAR_TERRAIN_DISTANCE {
+            let tile = camera_tile + IVec2::new(x, z);
+            if !far_terrain.tiles.contains_key(&tile) && !far_terrain.pending.contains(&tile) {
+                missing.push(tile);
+            }
+        }
+    }
+    missing.sort_by_key(|tile| (*tile - camera_tile).as_ivec2().abs().max_element());
+
+    let task_pool = AsyncComputeTaskPool::get();
+    for tile in missing.into_iter().take(free_tasks) {
+        let settings = settings.clone();
+        let built = far_terrain.built.clone();
+        let generation = far_terrain.generation;
+        task_pool
+            .spawn(async move {
+                let mesh = build_tile_mesh(&WorldGenerator::new(&settings), tile);
+                built.lock().unwrap().push((generation, tile, mesh));
+            })
+            .detach();
+        far_terrain.pending.insert(tile);
+    }
+}
+
+fn spawn_far_tiles(
+    mut commands: Commands,
+    chunk_manager: Res<ChunkManager>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut far_terrain: ResMut<FarTerrain>,
+) {
+    let built = std::mem::take(&mut *far_terrain.built.lock().unwrap());
+    for (generation, tile, mesh) in built {
+        if generation != far_terrain.generation || !far_terrain.pending.remove(&tile) {
+            continue;
+        }
+        let origin = tile * FAR_TILE_SIZE as i64;
+        let entity = commands
+            .spawn(MaterialMeshBundle {
+                mesh: meshes.add(mesh),
+                material: far_terrain.material_handle.clone(),
+                transform: Transform::from_translation(
+                    chunk_manager.world_to_render(I64Vec3::new(origin.x, 0, origin.y)),
+                ),
+                ..default()
+            })
+            .insert(NotShadowCaster)
+            .insert(FarTile)
+            .insert(Name::new(format!("Far Tile {tile}")))
+            .id();
+        far_terrain.tiles.insert(tile, entity);
+    }
+}
+
+/// Hide the tiles lying completely within the columns of loaded chunks. Tiles only partly
+/// covered stay visible, sunk below the real terrain
+fn hide_loaded_far_tiles(
+    chunk_manager: Res<ChunkManager>,
+    far_terrain: Res<FarTerrain>,
+    mut tile_query: Query<&mut Visibility, With<FarTile>>,
+) {
+    // Relative to the camera's chunk column, as the tiles are all around it
+    let chunk_size = CHUNK_SIZE as i32;
+    let camera_column = chunk_manager.camera_chunk_pos().xz() * chunk_size as i64;
+    let render_distance = chunk_manager.render_distance();
+    let loaded_min = IVec2::splat(-render_distance * chunk_size);
+    let loaded_max = IVec2::splat((render_distance + 1) * chunk_size);
+
+    for (tile, entity) in far_terrain.tiles.iter() {
+        let Ok(mut visibility) = tile_query.get_mut(*entity) else { continue; };
+        let tile_min = (*tile * FAR_TILE_SIZE as i64 - camera_column).as_ivec2();
+        let tile_max = tile_min + FAR_TILE_SIZE;
+        let loaded = tile_min.cmpge(loaded_min).all() && tile_max.cmple(loaded_max).all();
+        let wanted = if loaded {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+}
+
+/// Move the fade band with the loaded chunks, so the far terrain is dithered in over the
+/// outermost ones and is fully drawn past them
+fn update_far_terrain_fade(
+    chunk_manager: Res<ChunkManager>,
+    far_terrain: Res<FarTerrain>,
+    mut materials: ResMut<Assets<FarTerrainMaterial>>,
+) {
+    let chunk_size = CHUNK_SIZE as f32;
+    // Voxels are centred on their positions, so chunks start half a voxel before theirs
+    let center = chunk_manager
+        .chunk_translation(chunk_manager.camera_chunk_pos())
+        .xz()
+        + (chunk_size - 1.0) / 2.0;
+    let loaded_edge = (chunk_manager.render_distance() as f32 + 0.5) * chunk_size;
+    let fade = Vec4::new(
+        center.x,
+        center.y,
+        (loaded_edge - FAR_TERRAIN_FADE).max(0.0),
+        loaded_edge,
+    );
+    // Only touched when it moves, as every change uploads the material again
+    let Some(material) = materials.get(&far_terrain.material_handle) else { return; };
+    if material.fade == fade {
+        return;
+    }
+    if let Some(material) = materials.get_mut(&far_terrain.material_handle) {
+        material.fade = fade;
+    }
+}
+
+fn camera_tile(chunk_manager: &ChunkManager) -> I64Vec2 {
+    let camera_pos = chunk_manager.camera_chunk_pos().xz() * CHUNK_SIZE as i64;
+    camera_pos.div_euclid(FAR_TILE_SIZE as i64)
+}
+
+/// Grid mesh over the top of the tile's columns, relative to the tile's origin, coloured by
+/// the block on top. The samples on the tile's edges are shared with its neighbours, so
+/// tiles meet without gaps
+pub fn build_tile_mesh(generator: &WorldGenerator, tile: I64Vec2) -> Mesh {
+    let origin = tile * FAR_TILE_SIZE as i64;
+    let bottom = generator.settings().sea_level - SURFACE_SEARCH_DEPTH;
+    // One sample more on every side, to get the normals on the edges right
+    let side = SAMPLES_PER_SIDE + 2;
+    let mut heights = Vec::with_capacity(side * side);
+    let mut colors = Vec::with_capacity(side * side);
+    for z in 0..side {
+        for x in 0..side {
+            let sample_x = origin.x + ((x as i32 - 1) * FAR_SAMPLE_SPACING) as i64;
+            let sample_z = origin.y + ((z as i32 - 1) * FAR_SAMPLE_SPACING) as i64;
+            match generator.column_surface(sample_x, sample_z) {
+                Some(surface) => {
+                    let [r, g, b] = surface.top_type.map_color();
+                    heights.push(surface.top_height as f32 + 0.5);
+                    colors.push(Color::rgb_u8(r, g, b).as_linear_rgba_f32());
+                }
+                None => {
+                    heights.push(bottom as f32);
+                    colors.push(Color::BLACK.as_linear_rgba_f32());
+                }
+            }
+        }
+    }
+
+    let height = |x: usize, z: usize| heights[z * side + x];
+    let mut positions = Vec::with_capacity(SAMPLES_PER_SIDE * SAMPLES_PER_SIDE);
+    let mut normals = Vec::with_capacity(SAMPLES_PER_SIDE * SAMPLES_PER_SIDE);
+    let mut vertex_colors = Vec::with_capacity(SAMPLES_PER_SIDE * SAMPLES_PER_SIDE);
+    for z in 1..=SAMPLES_PER_SIDE {
+        for x in 1..=SAMPLES_PER_SIDE {
+            positions.push([
+                ((x - 1) as i32 * FAR_SAMPLE_SPACING) as f32,
+                height(x, z) - FAR_TERRAIN_SINK,
+                ((z - 1) as i32 * FAR_SAMPLE_SPACING) as f32,
+            ]);
+            let normal = Vec3::new(
+                height(x - 1, z) - height(x + 1, z),
+                2.0 * FAR_SAMPLE_SPACING as f32,
+                height(x, z - 1) - height(x, z + 1),
+            )
+            .normalize();
+            normals.push(normal.to_array());
+            vertex_colors.push(colors[z * side + x]);
+        }
+    }
+
+    let mut indices = Vec::with_capacity((SAMPLES_PER_SIDE - 1) * (SAMPLES_PER_SIDE - 1) * 6);
+    for z in 0..SAMPLES_PER_SIDE - 1 {
+        for x in 0..SAMPLES_PER_SIDE - 1 {
+            let index = (z * SAMPLES_PER_SIDE + x) as u32;
+            let next_row = index + SAMPLES_PER_SIDE as u32;
+            indices.extend([
+                index,
+                next_row,
+                index + 1,
+                index + 1,
+                next_row,
+                next_row + 1,
+            ]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, vertex_colors);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
@@ -0,0 +1,43 @@
+use std::{fs, time::SystemTime};
+
+use bevy::{prelude::*, utils::Duration};
+
+/// How often watched files are checked for being saved
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls the modification time of a file, for reloading it whenever it's saved
+pub struct FileWatcher {
+    pub path: String,
+    pub timer: Timer,
+    last_modified: Option<SystemTime>,
+}
+
+impl FileWatcher {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            timer: Timer::new(WATCH_INTERVAL, TimerMode::Repeating),
+            last_modified: modified_time(path),
+        }
+    }
+
+    /// Whether the file was saved since it was last seen, checked once every `WATCH_INTERVAL`
+    pub fn changed(&mut self, delta: Duration) -> bool {
+        if !self.timer.tick(delta).just_finished() {
+            return false;
+        }
+
+        let modified = modified_time(&self.path);
+        if modified == self.last_modified {
+            return false;
+        }
+        self.last_modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+use crate::{chunk::CHUNK_SIZE, chunk_manager::ChunkManager, world_coords::I64Vec3};
+
+/// How far the camera may get from the origin before everything is moved back around it,
+/// in voxels along any axis. Well within where f32 positions are still finer than a voxel
+pub const REBASE_DISTANCE: f32 = 512.0;
+
+/// Keeps the camera near the origin of the render and physics space, so positions there
+/// stay precise however far out the world goes. World positions are the chunk the space is
+/// centered on, `ChunkManager::origin`, plus the offset from it in the transforms. Once the
+/// camera is `REBASE_DISTANCE` away, the origin moves to its chunk and every top-level
+/// transform moves back by as much
+pub struct FloatingOriginPlugin;
+
+impl Plugin for FloatingOriginPlugin {
+    fn build(&self, app: &mut App) {
+        // Before anything is spawned for the frame, so nothing is placed around the old origin
+        app.add_system(rebase_origin.in_base_set(CoreSet::First));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn rebase_origin(
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut camera_query: Query<&mut Transform, With<Camera3d>>,
+    mut top_level_query: Query<&mut Transform, (Without<Parent>, Without<Node>, Without<Camera3d>)>,
+) {
+    let Ok(mut camera_transform) = camera_query.get_single_mut() else { return; };
+    let camera_translation = camera_transform.translation;
+    let new_origin = if !chunk_manager.floating_origin {
+        // Turned off, everything goes back to the world's own origin
+        I64Vec3::ZERO
+    } else if camera_translation.abs().max_element() > REBASE_DISTANCE {
+        chunk_manager.chunk_pos_at(camera_translation)
+    } else {
+        return;
+    };
+    let shift = new_origin - chunk_manager.origin();
+    if shift == I64Vec3::ZERO {
+        return;
+    }
+
+    // Whole chunks, so the offset is exact. Only ever as far as the camera got from the old
+    // origin, when the world's origin is turned off it may be further than f32 reaches
+    let offset = (shift * CHUNK_SIZE as i64).as_vec3();
+    camera_transform.translation -= offset;
+    for mut transform in top_level_query.iter_mut() {
+        transform.translation -= offset;
+    }
+    chunk_manager.set_origin(new_origin);
+}
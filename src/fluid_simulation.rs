@@ -0,0 +1,447 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::utils::hashbrown::{HashMap, HashSet};
+
+use crate::{
+    chunk_manager::ChunkManager,
+    voxel::{Voxel, VoxelType, FLUID_SOURCE_LEVEL, FULL_DENSITY},
+    world_coords::I64Vec3,
+    world_gen_settings::WorldGenSettings,
+};
+
+pub const FLUID_TICK_SECONDS: f32 = 0.25;
+pub const MAX_FLUID_UPDATES_PER_TICK: usize = 512;
+/// Fluid falling down keeps the highest level below a source, so it spreads out when landing
+pub const FLUID_FALLING_LEVEL: u8 = FLUID_SOURCE_LEVEL - 1;
+
+const STONE: Voxel = Voxel {
+    active: true,
+    voxel_type: VoxelType::Stone,
+    fluid_level: 0,
+    density: FULL_DENSITY,
+};
+const HORIZONTAL: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+const ADJACENT: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Tick driven cellular automaton letting water and lava flow
+///
+/// Only voxels next to an edit are scheduled, so still fluid costs nothing.
+/// All changes of a tick are applied as one batch with `ChunkManager::update_voxels`.
+pub struct FluidSimulationPlugin;
+
+impl Plugin for FluidSimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(simulate_fluids)
+            .init_resource::<FluidSimulation>();
+    }
+}
+
+#[derive(Resource)]
+pub struct FluidSimulation {
+    pub timer: Timer,
+    active_cells: VecDeque<I64Vec3>,
+    scheduled: HashSet<I64Vec3>,
+    /// Cells next to a chunk that isn't loaded, by that chunk. They're scheduled again once
+    /// it loads, as until then there's no telling where they flow
+    waiting: HashMap<I64Vec3, Vec<I64Vec3>>,
+}
+
+impl Default for FluidSimulation {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(FLUID_TICK_SECONDS, TimerMode::Repeating),
+            active_cells: VecDeque::new(),
+            scheduled: HashSet::new(),
+            waiting: HashMap::new(),
+        }
+    }
+}
+
+impl FluidSimulation {
+    /// Schedule the voxel at world_pos and its neighbours to be looked at next tick
+    pub fn wake(&mut self, world_pos: I64Vec3) {
+        self.schedule(world_pos);
+        for offset in ADJACENT {
+            self.schedule(world_pos + offset);
+        }
+    }
+
+    /// Forget every scheduled voxel, like when the world they were in is regenerated
+    pub fn clear(&mut self) {
+        self.active_cells.clear();
+        self.scheduled.clear();
+        self.waiting.clear();
+    }
+
+    fn schedule(&mut self, world_pos: I64Vec3) {
+        if self.scheduled.insert(world_pos) {
+            self.active_cells.push_back(world_pos);
+        }
+    }
+
+    /// Schedule the cells waiting for chunks that are loaded now, and forget the ones whose
+    /// own chunk was unloaded meanwhile
+    fn reschedule_waiting(&mut self, chunk_manager: &ChunkManager) {
+        let loaded: Vec<I64Vec3> = self
+            .waiting
+            .keys()
+            .filter(|chunk_pos| chunk_manager.chunk(chunk_pos).is_some())
+            .copied()
+            .collect();
+        for chunk_pos in loaded {
+            for world_pos in self.waiting.remove(&chunk_pos).unwrap_or_default() {
+                self.schedule(world_pos);
+            }
+        }
+        self.waiting.retain(|_, cells| {
+            cells.retain(|world_pos| get_cell(chunk_manager, world_pos).is_some());
+            !cells.is_empty()
+        });
+    }
+
+    pub fn tick(&mut self, chunk_manager: &mut ChunkManager) {
+        self.reschedule_waiting(chunk_manager);
+
+        let mut changes = HashMap::<I64Vec3, Voxel>::new();
+        let mut cells_updated = 0;
+        while let Some(world_pos) = self.active_cells.pop_front() {
+            self.scheduled.remove(&world_pos);
+            if let Err(chunk_pos) = update_cell(chunk_manager, &world_pos, &mut changes) {
+                self.waiting.entry(chunk_pos).or_default().push(world_pos);
+            }
+
+            cells_updated += 1;
+            if cells_updated >= MAX_FLUID_UPDATES_PER_TICK {
+                break;
+            }
+        }
+
+        if !changes.is_empty() {
+            let edits: Vec<(I64Vec3, Voxel)> = changes.into_iter().collect();
+            chunk_manager.update_voxels(&edits);
+        }
+    }
+}
+
+fn simulate_fluids(
+    time: Res<Time>,
+    settings: Res<WorldGenSettings>,
+    mut fluid_simulation: ResMut<FluidSimulation>,
+    mut chunk_manager: ResMut<ChunkManager>,
+) {
+    // The world is regenerated, nothing scheduled in the old one applies anymore
+    if settings.is_changed() {
+        fluid_simulation.clear();
+        return;
+    }
+
+    // Anything edited, by the player or by the last tick, may set fluid in motion
+    for world_pos in chunk_manager.take_edited_voxels() {
+        fluid_simulation.wake(world_pos);
+    }
+
+    if fluid_simulation.timer.tick(time.delta()).just_finished() {
+        fluid_simulation.tick(&mut chunk_manager);
+    }
+}
+
+/// How much the level drops for every voxel the fluid flows sideways
+fn flow_decay(voxel_type: VoxelType) -> u8 {
+    match voxel_type {
+        VoxelType::Lava => 2,
+        _ => 1,
+    }
+}
+
+fn is_source(voxel: &Voxel, voxel_type: VoxelType) -> bool {
+    voxel.voxel_type == voxel_type && voxel.fluid_level >= FLUID_SOURCE_LEVEL
+}
+
+/// The voxel, or None where its chunk isn't loaded and it's unknown
+fn get_cell(chunk_manager: &ChunkManager, world_pos: &I64Vec3) -> Option<Voxel> {
+    chunk_manager.get_world_voxel(world_pos).ok().copied()
+}
+
+/// Queue a change, merging it with any change already made to the same voxel this tick
+fn set_cell(
+    chunk_manager: &ChunkManager,
+    changes: &mut HashMap<I64Vec3, Voxel>,
+    world_pos: I64Vec3,
+    voxel: Voxel,
+) {
+    let merged = match changes.get(&world_pos) {
+        Some(queued) if queued.voxel_type == VoxelType::Stone => *queued,
+        Some(queued) if queued.voxel_type == voxel.voxel_type => {
+            if queued.fluid_level >= voxel.fluid_level {
+                *queued
+            } else {
+                voxel
+            }
+        }
+        Some(queued) if queued.voxel_type.is_fluid() && !voxel.voxel_type.is_fluid() => *queued,
+        _ => voxel,
+    };
+
+    let unchanged = get_cell(chunk_manager, &world_pos).is_some_and(|current| {
+        current.voxel_type == merged.voxel_type
+            && current.active == merged.active
+            && current.fluid_level == merged.fluid_level
+    });
+    if unchanged {
+        changes.remove(&world_pos);
+    } else {
+        changes.insert(world_pos, merged);
+    }
+}
+
+/// Let the fluid in the cell flow. Fails with the chunk to wait for if one of the cells
+/// around it isn't loaded, leaving everything as it is
+fn update_cell(
+    chunk_manager: &ChunkManager,
+    world_pos: &I64Vec3,
+    changes: &mut HashMap<I64Vec3, Voxel>,
+) -> Result<(), I64Vec3> {
+    // The cell's own chunk was unloaded since it was scheduled, it's generated afresh
+    let Some(voxel) = get_cell(chunk_manager, world_pos) else { return Ok(()); };
+    if !voxel.voxel_type.is_fluid() {
+        return Ok(());
+    }
+    let fluid_type = voxel.voxel_type;
+
+    // In the order of `ADJACENT`
+    let mut neighbours = [voxel; 6];
+    for (neighbour, offset) in neighbours.iter_mut().zip(ADJACENT) {
+        let neighbour_pos = *world_pos + offset;
+        *neighbour = get_cell(chunk_manager, &neighbour_pos)
+            .ok_or_else(|| ChunkManager::world_to_chunk_coords(&neighbour_pos).0)?;
+    }
+    let [right, left, above, below, front, back] = neighbours;
+    let horizontal = [right, left, front, back];
+
+    // Water and lava touching turns the lava into stone
+    let mut touches_other_fluid = false;
+    for (neighbour, offset) in neighbours.iter().zip(ADJACENT) {
+        let neighbour_pos = *world_pos + offset;
+        if neighbour.voxel_type.is_fluid() && neighbour.voxel_type != fluid_type {
+            touches_other_fluid = true;
+            if neighbour.voxel_type == VoxelType::Lava {
+                set_cell(chunk_manager, changes, neighbour_pos, STONE);
+            }
+        }
+    }
+    if touches_other_fluid && fluid_type == VoxelType::Lava {
+        set_cell(chunk_manager, changes, *world_pos, STONE);
+        return Ok(());
+    }
+
+    let below_pos = *world_pos - IVec3::Y;
+    let below_is_floor =
+        (below.active && !below.voxel_type.is_fluid()) || is_source(&below, fluid_type);
+
+    // Flowing fluid takes its level from the neighbours feeding it
+    let mut level = voxel.fluid_level;
+    if level < FLUID_SOURCE_LEVEL {
+        let source_count = horizontal
+            .iter()
+            .filter(|neighbour| is_source(neighbour, fluid_type))
+            .count();
+
+        level = if fluid_type == VoxelType::Water && source_count >= 2 && below_is_floor {
+            // Between two water sources, on top of something to hold it, becomes an infinite source
+            FLUID_SOURCE_LEVEL
+        } else if above.voxel_type == fluid_type {
+            FLUID_FALLING_LEVEL
+        } else {
+            horizontal
+                .iter()
+                .filter(|neighbour| neighbour.voxel_type == fluid_type)
+                .map(|neighbour| neighbour.fluid_level.saturating_sub(flow_decay(fluid_type)))
+                .max()
+                .unwrap_or(0)
+        };
+
+        if level == 0 {
+            set_cell(chunk_manager, changes, *world_pos, Voxel::new_empty());
+            return Ok(());
+        }
+        if level != voxel.fluid_level {
+            set_cell(
+                chunk_manager,
+                changes,
+                *world_pos,
+                Voxel::new_fluid(fluid_type, level),
+            );
+        }
+    }
+
+    // Fall down first, and only spread sideways once something holds the fluid up
+    if !below.active {
+        set_cell(
+            chunk_manager,
+            changes,
+            below_pos,
+            Voxel::new_fluid(fluid_type, FLUID_FALLING_LEVEL),
+        );
+        return Ok(());
+    }
+    if !below_is_floor {
+        return Ok(());
+    }
+
+    let spread_level = level.saturating_sub(flow_decay(fluid_type));
+    if spread_level == 0 {
+        return Ok(());
+    }
+    for (neighbour, offset) in horizontal.iter().zip(HORIZONTAL) {
+        let neighbour_pos = *world_pos + offset;
+        let flows_into = !neighbour.active
+            || (neighbour.voxel_type == fluid_type && neighbour.fluid_level < spread_level);
+        if flows_into {
+            set_cell(
+                chunk_manager,
+                changes,
+                neighbour_pos,
+                Voxel::new_fluid(fluid_type, spread_level),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen_settings::Generator;
+
+    /// A stone floor at y 0, in the loaded chunks at the given chunk heights around the origin
+    fn flat_world(chunk_heights: &[i64]) -> ChunkManager {
+        let settings = WorldGenSettings {
+            generator: Generator::Flat {
+                layers: "1*stone".parse().unwrap(),
+                base_height: 0,
+            },
+            ..default()
+        };
+        let mut chunk_manager = ChunkManager::with_world_gen_settings(&settings);
+        for &y in chunk_heights {
+            for x in -1..=1 {
+                for z in -1..=1 {
+                    chunk_manager.load_chunk(I64Vec3::new(x, y, z));
+                }
+            }
+        }
+        chunk_manager
+    }
+
+    fn run(simulation: &mut FluidSimulation, chunk_manager: &mut ChunkManager, ticks: usize) {
+        for _ in 0..ticks {
+            for world_pos in chunk_manager.take_edited_voxels() {
+                simulation.wake(world_pos);
+            }
+            simulation.tick(chunk_manager);
+        }
+    }
+
+    fn cell(chunk_manager: &ChunkManager, x: i64, y: i64, z: i64) -> Voxel {
+        get_cell(chunk_manager, &I64Vec3::new(x, y, z)).unwrap()
+    }
+
+    fn source(voxel_type: VoxelType) -> Voxel {
+        Voxel::new_fluid(voxel_type, FLUID_SOURCE_LEVEL)
+    }
+
+    #[test]
+    fn fluid_falls_before_spreading() {
+        let mut chunk_manager = flat_world(&[0]);
+        let mut simulation = FluidSimulation::default();
+        chunk_manager.update_voxels(&[(I64Vec3::new(4, 5, 4), source(VoxelType::Water))]);
+        run(&mut simulation, &mut chunk_manager, 1);
+
+        let below = cell(&chunk_manager, 4, 4, 4);
+        assert_eq!(below.voxel_type, VoxelType::Water);
+        assert_eq!(below.fluid_level, FLUID_FALLING_LEVEL);
+        // Nothing holds the source up, so it doesn't spread sideways
+        assert!(!cell(&chunk_manager, 5, 5, 4).active);
+    }
+
+    #[test]
+    fn flowing_fluid_loses_a_level_per_voxel() {
+        for (fluid_type, decay) in [(VoxelType::Water, 1), (VoxelType::Lava, 2)] {
+            let mut chunk_manager = flat_world(&[0]);
+            let mut simulation = FluidSimulation::default();
+            chunk_manager.update_voxels(&[(I64Vec3::new(4, 1, 4), source(fluid_type))]);
+            run(&mut simulation, &mut chunk_manager, 16);
+
+            for distance in 1..FLUID_SOURCE_LEVEL / decay {
+                let voxel = cell(&chunk_manager, 4 + distance as i64, 1, 4);
+                assert_eq!(voxel.voxel_type, fluid_type);
+                assert_eq!(voxel.fluid_level, FLUID_SOURCE_LEVEL - distance * decay);
+            }
+            let past_end = cell(
+                &chunk_manager,
+                4 + (FLUID_SOURCE_LEVEL / decay) as i64,
+                1,
+                4,
+            );
+            assert!(!past_end.active);
+        }
+    }
+
+    #[test]
+    fn water_between_two_sources_becomes_a_source() {
+        let mut chunk_manager = flat_world(&[0]);
+        let mut simulation = FluidSimulation::default();
+        chunk_manager.update_voxels(&[
+            (I64Vec3::new(3, 1, 4), source(VoxelType::Water)),
+            (I64Vec3::new(5, 1, 4), source(VoxelType::Water)),
+        ]);
+        run(&mut simulation, &mut chunk_manager, 4);
+
+        assert_eq!(
+            cell(&chunk_manager, 4, 1, 4).fluid_level,
+            FLUID_SOURCE_LEVEL
+        );
+        // With a single source next to it water keeps flowing
+        assert!(cell(&chunk_manager, 2, 1, 4).fluid_level < FLUID_SOURCE_LEVEL);
+    }
+
+    #[test]
+    fn lava_touching_water_turns_into_stone() {
+        let mut chunk_manager = flat_world(&[0]);
+        let mut simulation = FluidSimulation::default();
+        chunk_manager.update_voxels(&[
+            (I64Vec3::new(4, 1, 4), source(VoxelType::Lava)),
+            (I64Vec3::new(5, 1, 4), source(VoxelType::Water)),
+        ]);
+        run(&mut simulation, &mut chunk_manager, 1);
+
+        assert_eq!(cell(&chunk_manager, 4, 1, 4).voxel_type, VoxelType::Stone);
+        assert_eq!(cell(&chunk_manager, 5, 1, 4).voxel_type, VoxelType::Water);
+    }
+
+    #[test]
+    fn fluid_waits_for_the_chunk_below_to_load() {
+        // The floor's chunk isn't loaded, fluid at the bottom of the chunk above can't tell
+        // whether it stands on anything
+        let mut chunk_manager = flat_world(&[1]);
+        let mut simulation = FluidSimulation::default();
+        chunk_manager.update_voxels(&[(I64Vec3::new(4, 16, 4), source(VoxelType::Water))]);
+        run(&mut simulation, &mut chunk_manager, 4);
+        assert!(!cell(&chunk_manager, 5, 16, 4).active);
+
+        chunk_manager.load_chunk(I64Vec3::ZERO);
+        run(&mut simulation, &mut chunk_manager, 1);
+        let below = cell(&chunk_manager, 4, 15, 4);
+        assert_eq!(below.voxel_type, VoxelType::Water);
+        assert_eq!(below.fluid_level, FLUID_FALLING_LEVEL);
+        assert!(!cell(&chunk_manager, 5, 16, 4).active);
+    }
+}
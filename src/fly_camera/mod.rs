@@ -1,5 +1,3 @@
-use std::f32::EPSILON;
-
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
 
@@ -98,7 +96,7 @@ fn camera_movement_system(
         direction -= Vec3::Y;
     }
 
-    if direction.length_squared() < EPSILON {
+    if direction.length_squared() < f32::EPSILON {
         return;
     }
 
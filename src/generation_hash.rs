@@ -0,0 +1,266 @@
+use bevy::prelude::IVec3;
+
+use crate::{
+    chunk::Chunk,
+    chunk_manager::ChunkManager,
+    structures::{StructureTemplate, STRUCTURES_PATH},
+    world_coords::I64Vec3,
+    world_gen_settings::{WorldGenSettings, WORLD_GEN_SETTINGS_PATH},
+};
+
+/// Region hashed when no other is given, wide enough to hold some structures
+pub const DEFAULT_HASH_REGION: (I64Vec3, I64Vec3) =
+    (I64Vec3::new(-6, -3, -6), I64Vec3::new(5, 1, 5));
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a, used instead of `DefaultHasher` as the hashes are stored in golden files
+/// and have to stay the same across platforms and Rust versions
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+pub fn hash_chunk(chunk: &Chunk) -> u64 {
+    chunk.voxels.iter().fold(FNV_OFFSET_BASIS, |hash, voxel| {
+        hash_bytes(
+            hash,
+            &[
+                voxel.active as u8,
+                voxel.voxel_type as u8,
+                voxel.fluid_level,
+                voxel.density,
+            ],
+        )
+    })
+}
+
+/// Chunk positions from `min` to `max`, inclusive, ordered by x, then y, then z
+pub fn region_positions(min: I64Vec3, max: I64Vec3) -> Vec<I64Vec3> {
+    let mut positions = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                positions.push(I64Vec3::new(x, y, z));
+            }
+        }
+    }
+    positions
+}
+
+/// Hash of the loaded chunks from `min` to `max`, as they would be meshed. The voxel data of
+/// every chunk is updated first, so its neighbours have to be loaded as well
+pub fn hash_region(chunk_manager: &mut ChunkManager, min: I64Vec3, max: I64Vec3) -> u64 {
+    let positions = region_positions(min, max);
+    for chunk_pos in positions.iter() {
+        chunk_manager.update_voxel_data(*chunk_pos);
+    }
+
+    positions.iter().fold(FNV_OFFSET_BASIS, |hash, chunk_pos| {
+        let chunk_hash = chunk_manager
+            .get_chunk(chunk_pos)
+            .map_or(0, |chunk| hash_chunk(chunk));
+        hash_bytes(hash, &chunk_hash.to_le_bytes())
+    })
+}
+
+/// Generate the chunks from `min` to `max` like the game does, with the structures from
+/// `STRUCTURES_PATH`, and hash them. A margin of one chunk is loaded around the region,
+/// for the structures reaching in from outside of it
+pub fn hash_generated_region(settings: &WorldGenSettings, min: I64Vec3, max: I64Vec3) -> u64 {
+    let mut chunk_manager = ChunkManager::with_world_gen_settings(settings);
+    chunk_manager.set_structures(StructureTemplate::load_dir(STRUCTURES_PATH));
+    for chunk_pos in region_positions(min - IVec3::ONE, max + IVec3::ONE) {
+        chunk_manager.load_chunk(chunk_pos);
+    }
+    hash_region(&mut chunk_manager, min, max)
+}
+
+/// Print the hash of a region generated from the settings and structures on disk. Takes the
+/// min and max chunk positions as six numbers, or nothing for `DEFAULT_HASH_REGION`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let (min, max) = match args.len() {
+        0 => DEFAULT_HASH_REGION,
+        6 => {
+            let mut values = [0; 6];
+            for (value, arg) in values.iter_mut().zip(args) {
+                *value = arg
+                    .parse()
+                    .map_err(|_| format!("invalid chunk coordinate {arg}"))?;
+            }
+            (
+                I64Vec3::new(values[0], values[1], values[2]),
+                I64Vec3::new(values[3], values[4], values[5]),
+            )
+        }
+        _ => {
+            return Err(
+                "usage: --generation-hash [min_x min_y min_z max_x max_y max_z]".to_string(),
+            )
+        }
+    };
+
+    let settings = WorldGenSettings::load_or_default(WORLD_GEN_SETTINGS_PATH);
+    let hash = hash_generated_region(&settings, min, max);
+    println!("Generation hash of chunks {min} to {max}: {hash:016x}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, env, fs, thread};
+
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::{
+        world_gen_settings::{FlatLayers, Generator},
+        world_generator::WorldGenerator,
+    };
+
+    const GOLDEN_PATH: &str = "tests/golden/generation_hashes.ron";
+    /// Set to write the current hashes to the golden file, after an intended generator change
+    const UPDATE_GOLDEN_VAR: &str = "UPDATE_GOLDEN";
+    const REGION_MIN: I64Vec3 = DEFAULT_HASH_REGION.0;
+    const REGION_MAX: I64Vec3 = DEFAULT_HASH_REGION.1;
+
+    fn golden_cases() -> Vec<(&'static str, WorldGenSettings)> {
+        let flat_layers: FlatLayers = "1*bedrock,3*dirt,1*grass".parse().unwrap();
+        let density = ron::from_str(
+            "Warp(
+                source: Select(
+                    a: Add(Gradient(height: 4.0, falloff: 0.05), Fbm(frequency: 0.01, octaves: 4)),
+                    b: Clamp(source: Gradient(height: 20.0, falloff: 1.0), min: -1.0, max: 1.0),
+                    control: Worley(seed: 3, frequency: 0.004),
+                    min: 0.6, max: 1.0, falloff: 0.05,
+                ),
+                frequency: 0.02, power: 8.0,
+            )",
+        )
+        .unwrap();
+
+        vec![
+            ("perlin_default", WorldGenSettings::default()),
+            (
+                "perlin_seed_42_octaves_3",
+                WorldGenSettings {
+                    seed: 42,
+                    octaves: 3,
+                    ..WorldGenSettings::default()
+                },
+            ),
+            (
+                "flat",
+                WorldGenSettings {
+                    generator: Generator::Flat {
+                        layers: flat_layers,
+                        base_height: -3,
+                    },
+                    ..WorldGenSettings::default()
+                },
+            ),
+            (
+                "noise_graph",
+                WorldGenSettings {
+                    generator: Generator::Noise { density },
+                    threshold: 0.0,
+                    ..WorldGenSettings::default()
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn generation_matches_golden_hashes() {
+        let hashes: BTreeMap<String, String> = golden_cases()
+            .iter()
+            .map(|(name, settings)| {
+                let hash = hash_generated_region(settings, REGION_MIN, REGION_MAX);
+                (name.to_string(), format!("{hash:016x}"))
+            })
+            .collect();
+
+        if env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+            let contents = ron::ser::to_string_pretty(&hashes, Default::default()).unwrap();
+            fs::write(GOLDEN_PATH, contents + "\n").unwrap();
+            return;
+        }
+
+        let contents = fs::read_to_string(GOLDEN_PATH).unwrap();
+        let golden: BTreeMap<String, String> = ron::from_str(&contents).unwrap();
+        for (name, hash) in hashes.iter() {
+            assert_eq!(
+                golden.get(name),
+                Some(hash),
+                "generation of \"{name}\" changed, run the tests with {UPDATE_GOLDEN_VAR}=1 if that was intended"
+            );
+        }
+    }
+
+    #[test]
+    fn generation_is_independent_of_load_order() {
+        for (name, settings) in golden_cases() {
+            let mut positions = region_positions(REGION_MIN - IVec3::ONE, REGION_MAX + IVec3::ONE);
+            let in_order = hash_generated_region(&settings, REGION_MIN, REGION_MAX);
+
+            positions.shuffle(&mut StdRng::seed_from_u64(7));
+            let mut chunk_manager = ChunkManager::with_world_gen_settings(&settings);
+            chunk_manager.set_structures(StructureTemplate::load_dir(STRUCTURES_PATH));
+            for chunk_pos in positions {
+                chunk_manager.load_chunk(chunk_pos);
+            }
+            let shuffled = hash_region(&mut chunk_manager, REGION_MIN, REGION_MAX);
+
+            assert_eq!(
+                in_order, shuffled,
+                "load order changed generation of \"{name}\""
+            );
+        }
+    }
+
+    #[test]
+    fn generation_is_independent_of_thread_count() {
+        for (name, settings) in golden_cases() {
+            let positions = region_positions(REGION_MIN, REGION_MAX);
+            // A new generator for every pass, so the threads fill the lake basins themselves
+            // instead of reading the ones cached by the pass before
+            let generate = |generator: &WorldGenerator, chunk_pos: &I64Vec3| {
+                let mut chunk = Chunk::new();
+                chunk.setup_generated(*chunk_pos, generator);
+                hash_chunk(&chunk)
+            };
+
+            let generator = WorldGenerator::new(&settings);
+            let single_thread: Vec<u64> = positions
+                .iter()
+                .map(|chunk_pos| generate(&generator, chunk_pos))
+                .collect();
+            for threads in [2, 5] {
+                let generator = WorldGenerator::new(&settings);
+                let hashes: Vec<u64> = thread::scope(|scope| {
+                    let handles: Vec<_> = positions
+                        .chunks(positions.len().div_ceil(threads))
+                        .map(|positions| {
+                            scope.spawn(|| {
+                                positions
+                                    .iter()
+                                    .map(|chunk_pos| generate(&generator, chunk_pos))
+                                    .collect::<Vec<_>>()
+                            })
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .flat_map(|handle| handle.join().unwrap())
+                        .collect()
+                });
+                assert_eq!(
+                    single_thread, hashes,
+                    "generating \"{name}\" on {threads} threads changed it"
+                );
+            }
+        }
+    }
+}
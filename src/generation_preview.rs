@@ -0,0 +1,269 @@
+use std::{fs, path::Path, thread};
+
+use bevy::prelude::{IVec2, IVec3};
+use image::{Rgb, RgbImage};
+
+use crate::{
+    chunk::CHUNK_SIZE,
+    structures::{self, StructureAnchors, StructureTemplate, STRUCTURES_PATH},
+    voxel::VoxelType,
+    world_coords::{I64Vec2, I64Vec3},
+    world_gen_settings::{WorldGenSettings, WORLD_GEN_SETTINGS_PATH},
+    world_generator::{ColumnSurface, WorldGenerator, SURFACE_SEARCH_DEPTH, SURFACE_SEARCH_HEIGHT},
+};
+
+pub const DEFAULT_PREVIEW_SIZE: u32 = 1024;
+
+#[derive(Debug)]
+pub enum PreviewError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    InvalidArgument(String),
+}
+
+/// What a column of the world is covered by. There are no biomes yet, so the biome map
+/// shows the areas the generator tells apart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Region {
+    /// No ground within the surface search range
+    #[default]
+    Void,
+    Sea,
+    Lake,
+    Beach,
+    Land,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Column {
+    /// Height of the topmost solid voxel, not counting fluids on top of it
+    ground_height: i32,
+    /// Height and type of the topmost voxel, fluids included
+    top_height: i32,
+    top_type: Option<VoxelType>,
+    region: Region,
+}
+
+/// Top-down maps of an area, made from the same `WorldGenerator` and structures that
+/// `ChunkManager::load_chunks` fills chunks with
+pub struct GenerationPreview {
+    pub heightmap: RgbImage,
+    pub biome_map: RgbImage,
+    pub surface_map: RgbImage,
+}
+
+impl GenerationPreview {
+    /// Render the `size` by `size` columns starting at `min`, with x going right and z going down
+    pub fn render(
+        generator: &WorldGenerator,
+        structures: &[StructureTemplate],
+        min: I64Vec2,
+        size: u32,
+    ) -> Self {
+        let mut columns = generate_columns(generator, min, size);
+        place_structures(generator, structures, min, size, &mut columns);
+
+        let sea_level = generator.settings().sea_level;
+        let min_height = sea_level - SURFACE_SEARCH_DEPTH;
+        let max_height = sea_level + SURFACE_SEARCH_HEIGHT;
+        let brightness = |height: i32| {
+            (height.clamp(min_height, max_height) - min_height) as f32
+                / (max_height - min_height) as f32
+        };
+
+        let mut preview = GenerationPreview {
+            heightmap: RgbImage::new(size, size),
+            biome_map: RgbImage::new(size, size),
+            surface_map: RgbImage::new(size, size),
+        };
+        for (index, column) in columns.iter().enumerate() {
+            let (x, y) = (index as u32 % size, index as u32 / size);
+            let Some(top_type) = column.top_type else { continue; };
+
+            let gray = (brightness(column.ground_height) * 255.0) as u8;
+            preview.heightmap.put_pixel(x, y, Rgb([gray, gray, gray]));
+            preview
+                .biome_map
+                .put_pixel(x, y, region_color(column.region));
+            // Shade the blocks by height, so the shape of the terrain still shows
+            let shade = 0.6 + 0.4 * brightness(column.top_height);
+            let [r, g, b] = top_type.map_color();
+            preview.surface_map.put_pixel(
+                x,
+                y,
+                Rgb([
+                    (r as f32 * shade) as u8,
+                    (g as f32 * shade) as u8,
+                    (b as f32 * shade) as u8,
+                ]),
+            );
+        }
+        preview
+    }
+
+    /// Write the maps as `heightmap.png`, `biome_map.png` and `surface_map.png` into the directory
+    pub fn save(&self, dir: &Path) -> Result<(), PreviewError> {
+        fs::create_dir_all(dir).map_err(PreviewError::Io)?;
+        for (name, image) in [
+            ("heightmap.png", &self.heightmap),
+            ("biome_map.png", &self.biome_map),
+            ("surface_map.png", &self.surface_map),
+        ] {
+            image.save(dir.join(name)).map_err(PreviewError::Image)?;
+        }
+        Ok(())
+    }
+}
+
+/// Render a preview of the world from the settings and structures on disk, without
+/// opening a window. Takes the output directory, then optionally the size and the center x and z
+pub fn run(args: &[String]) -> Result<(), PreviewError> {
+    let invalid = |arg: &String| PreviewError::InvalidArgument(arg.clone());
+    let Some(out_dir) = args.first() else {
+        return Err(PreviewError::InvalidArgument(
+            "usage: --preview <out_dir> [size] [center_x center_z]".to_string(),
+        ));
+    };
+    let size = match args.get(1) {
+        Some(arg) => arg.parse::<u32>().map_err(|_| invalid(arg))?,
+        None => DEFAULT_PREVIEW_SIZE,
+    };
+    let mut center = I64Vec2::default();
+    if let (Some(x), Some(z)) = (args.get(2), args.get(3)) {
+        center.x = x.parse().map_err(|_| invalid(x))?;
+        center.y = z.parse().map_err(|_| invalid(z))?;
+    }
+
+    let settings = WorldGenSettings::load_or_default(WORLD_GEN_SETTINGS_PATH);
+    let generator = WorldGenerator::new(&settings);
+    let structures = StructureTemplate::load_dir(STRUCTURES_PATH);
+    let min = center - IVec2::splat(size as i32 / 2);
+
+    GenerationPreview::render(&generator, &structures, min, size).save(Path::new(out_dir))?;
+    println!("Wrote a {size}x{size} generation preview around {center} to {out_dir}");
+    Ok(())
+}
+
+/// Look up every column, splitting the rows over the available threads
+fn generate_columns(generator: &WorldGenerator, min: I64Vec2, size: u32) -> Vec<Column> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let rows_per_thread = (size as usize).div_ceil(threads).max(1);
+
+    let mut columns = vec![Column::default(); (size * size) as usize];
+    thread::scope(|scope| {
+        for (chunk_index, rows) in columns
+            .chunks_mut(rows_per_thread * size as usize)
+            .enumerate()
+        {
+            scope.spawn(move || {
+                let first_row = chunk_index * rows_per_thread;
+                for (index, column) in rows.iter_mut().enumerate() {
+                    let x = min.x + (index % size as usize) as i64;
+                    let z = min.y + (first_row + index / size as usize) as i64;
+                    *column = generate_column(generator, x, z);
+                }
+            });
+        }
+    });
+    columns
+}
+
+fn generate_column(generator: &WorldGenerator, x: i64, z: i64) -> Column {
+    let Some(ColumnSurface {
+        ground_height,
+        ground_type,
+        top_height,
+        top_type,
+    }) = generator.column_surface(x, z)
+    else {
+        return Column::default();
+    };
+
+    let sea_level = generator.settings().sea_level;
+    let region = if top_type == VoxelType::Water && top_height > ground_height {
+        if top_height <= sea_level {
+            Region::Sea
+        } else {
+            Region::Lake
+        }
+    } else if ground_type == VoxelType::Sand {
+        Region::Beach
+    } else {
+        Region::Land
+    };
+
+    Column {
+        ground_height,
+        top_height,
+        top_type: Some(top_type),
+        region,
+    }
+}
+
+/// Put the solid voxels of structures sticking out above the ground onto the columns
+fn place_structures(
+    generator: &WorldGenerator,
+    structures: &[StructureTemplate],
+    min: I64Vec2,
+    size: u32,
+    columns: &mut [Column],
+) {
+    let columns_with_ground = columns.iter().filter(|column| column.top_type.is_some());
+    let Some(min_height) = columns_with_ground.map(|column| column.ground_height).min() else { return; };
+    let max_height = columns
+        .iter()
+        .map(|column| column.top_height)
+        .max()
+        .unwrap_or(min_height);
+
+    let chunk_size = CHUNK_SIZE as i64;
+    let max = min + IVec2::splat(size as i32 - 1);
+    let mut anchors = StructureAnchors::default();
+    let (min_y, max_y) = (
+        (min_height - SURFACE_SEARCH_DEPTH) as i64,
+        max_height as i64 + 1,
+    );
+    for chunk_x in min.x.div_euclid(chunk_size)..=max.x.div_euclid(chunk_size) {
+        for chunk_z in min.y.div_euclid(chunk_size)..=max.y.div_euclid(chunk_size) {
+            for chunk_y in min_y.div_euclid(chunk_size)..=max_y.div_euclid(chunk_size) {
+                let chunk_pos = I64Vec3::new(chunk_x, chunk_y, chunk_z);
+                let chunk_min = chunk_pos * chunk_size;
+                let chunk_max = chunk_min + IVec3::splat(CHUNK_SIZE as i32 - 1);
+                for (world_pos, voxel) in structures::place_structures(
+                    structures,
+                    generator,
+                    &mut anchors,
+                    chunk_min,
+                    chunk_max,
+                ) {
+                    let (x, z) = (world_pos.x - min.x, world_pos.z - min.y);
+                    if !voxel.active || x < 0 || z < 0 || x >= size as i64 || z >= size as i64 {
+                        continue;
+                    }
+                    let column = &mut columns[(z as u32 * size + x as u32) as usize];
+                    // Only structures near the ground get here, so their heights fit an i32
+                    let height = world_pos.y as i32;
+                    if column.top_type.is_some() && height <= column.top_height {
+                        continue;
+                    }
+                    column.top_height = height;
+                    column.top_type = Some(voxel.voxel_type);
+                    if column.region == Region::Void {
+                        column.ground_height = height;
+                        column.region = Region::Land;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn region_color(region: Region) -> Rgb<u8> {
+    match region {
+        Region::Void => Rgb([0, 0, 0]),
+        Region::Sea => Rgb([28, 64, 160]),
+        Region::Lake => Rgb([64, 140, 220]),
+        Region::Beach => Rgb([230, 210, 140]),
+        Region::Land => Rgb([70, 150, 60]),
+    }
+}
@@ -5,22 +5,65 @@ use bevy::window::PresentMode;
 use voxel_interaction::VoxelInteractionPlugin;
 
 mod debug_info;
+mod emissive_map;
 mod fly_camera;
 use crate::debug_info::DebugInfoPlugin;
+use crate::fluid_simulation::FluidSimulationPlugin;
 use crate::fly_camera::{FlyCamera, FlyCameraPlugin};
 
+pub mod block_materials;
 pub mod chunk;
+mod chunk_connectivity;
 mod chunk_manager;
 mod chunk_mesh_builder;
+mod chunk_transition;
 pub mod face;
+mod far_terrain;
+mod file_watcher;
+mod floating_origin;
+mod fluid_simulation;
+pub mod generation_hash;
+pub mod generation_preview;
+pub mod noise_graph;
+mod padded_chunk;
+mod region_cache;
+mod render_settings;
+pub mod resource_packs;
+pub mod structures;
+mod texture_animation;
+pub mod tint_overrides;
 pub mod voxel;
+mod voxel_body;
 mod voxel_engine;
 mod voxel_interaction;
+mod voxel_material;
 pub mod voxel_textures;
+pub mod world_coords;
+pub mod world_gen_settings;
+pub mod world_generator;
 
 use voxel_engine::VoxelEnginePlugin;
 
 fn main() {
+    // `--preview <out_dir> [size] [center_x center_z]` renders maps of the world instead of running it,
+    // and `--generation-hash [min_x min_y min_z max_x max_y max_z]` prints the hash of a region of chunks
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("--preview") => {
+            if let Err(error) = generation_preview::run(&args[1..]) {
+                println!("Failed to render generation preview: {error:?}");
+            }
+            return;
+        }
+        Some("--generation-hash") => {
+            if let Err(error) = generation_hash::run(&args[1..]) {
+                println!("Failed to hash generation: {error}");
+            }
+            return;
+        }
+        _ => {}
+    }
+
     App::new()
         .add_plugins(
             DefaultPlugins
@@ -37,6 +80,7 @@ fn main() {
         .add_plugin(DebugInfoPlugin)
         .add_plugin(VoxelEnginePlugin)
         .add_plugin(VoxelInteractionPlugin)
+        .add_plugin(FluidSimulationPlugin)
         .add_startup_system(setup)
         .run();
 }
@@ -63,8 +107,7 @@ fn setup(mut commands: Commands) {
             translation: Vec3::new(-1.0, 2.0, 0.0),
             rotation: Quat::from_rotation_x(-1.5) * Quat::from_rotation_y(-0.3),
             ..default()
-        }
-        .into(),
+        },
         ..default()
     });
 }
@@ -0,0 +1,239 @@
+use noise::{
+    core::worley::{distance_functions, worley_3d, ReturnType},
+    permutationtable::PermutationTable,
+    Add, Clamp, Fbm, MultiFractal, Multiply, NoiseFn, Perlin, ScalePoint, Seedable, Select,
+    Simplex, Turbulence,
+};
+use serde::{Deserialize, Serialize};
+
+/// A compiled noise graph, giving the density at a world voxel position
+pub type DensityFn = Box<dyn NoiseFn<f64, 3> + Send + Sync>;
+
+/// Noise used as the base of a fractal
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Basis {
+    #[default]
+    Perlin,
+    Simplex,
+}
+
+/// Node of a density function, described in RON and compiled with `NoiseNode::build`.
+/// Nodes are sampled at world voxel positions, so frequencies are per voxel.
+/// Every `seed` is added to the world seed, so the graph changes along with it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NoiseNode {
+    Constant(f64),
+    /// `(height - y) * falloff`, positive below `height` and negative above it.
+    /// Adding noise to it gives terrain with a surface around `height`
+    Gradient {
+        #[serde(default)]
+        height: f64,
+        falloff: f64,
+    },
+    Perlin {
+        #[serde(default)]
+        seed: u32,
+        frequency: f64,
+    },
+    Simplex {
+        #[serde(default)]
+        seed: u32,
+        frequency: f64,
+    },
+    /// Cellular noise, a random value per cell, or the distance to the cell's point if `distance` is set
+    Worley {
+        #[serde(default)]
+        seed: u32,
+        frequency: f64,
+        #[serde(default)]
+        distance: bool,
+    },
+    /// Octaves of the basis noise, each at `lacunarity` times the frequency
+    /// and `persistence` times the amplitude of the one before
+    Fbm {
+        #[serde(default)]
+        basis: Basis,
+        #[serde(default)]
+        seed: u32,
+        frequency: f64,
+        octaves: usize,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f64,
+        #[serde(default = "default_persistence")]
+        persistence: f64,
+    },
+    /// Domain warp, moving the position the source is sampled at by up to `power` voxels
+    Warp {
+        source: Box<NoiseNode>,
+        #[serde(default)]
+        seed: u32,
+        frequency: f64,
+        power: f64,
+        #[serde(default = "default_roughness")]
+        roughness: usize,
+    },
+    Clamp {
+        source: Box<NoiseNode>,
+        min: f64,
+        max: f64,
+    },
+    /// `b` where `control` is between `min` and `max`, otherwise `a`.
+    /// The two are blended over `falloff` around the edges
+    Select {
+        a: Box<NoiseNode>,
+        b: Box<NoiseNode>,
+        control: Box<NoiseNode>,
+        min: f64,
+        max: f64,
+        #[serde(default)]
+        falloff: f64,
+    },
+    Add(Box<NoiseNode>, Box<NoiseNode>),
+    Multiply(Box<NoiseNode>, Box<NoiseNode>),
+}
+
+fn default_lacunarity() -> f64 {
+    2.0
+}
+
+fn default_persistence() -> f64 {
+    0.5
+}
+
+fn default_roughness() -> usize {
+    3
+}
+
+impl NoiseNode {
+    /// Compile the graph into a density function for the given world seed
+    pub fn build(&self, world_seed: u32) -> DensityFn {
+        let seeded = |seed: &u32| world_seed.wrapping_add(*seed);
+        match self {
+            NoiseNode::Constant(value) => Box::new(Constant(*value)),
+            NoiseNode::Gradient { height, falloff } => Box::new(Gradient {
+                height: *height,
+                falloff: *falloff,
+            }),
+            NoiseNode::Perlin { seed, frequency } => {
+                Box::new(ScalePoint::new(Perlin::new(seeded(seed))).set_scale(*frequency))
+            }
+            NoiseNode::Simplex { seed, frequency } => {
+                Box::new(ScalePoint::new(Simplex::new(seeded(seed))).set_scale(*frequency))
+            }
+            NoiseNode::Worley {
+                seed,
+                frequency,
+                distance,
+            } => Box::new(Worley {
+                perm_table: PermutationTable::new(seeded(seed)),
+                frequency: *frequency,
+                return_type: if *distance {
+                    ReturnType::Distance
+                } else {
+                    ReturnType::Value
+                },
+            }),
+            NoiseNode::Fbm {
+                basis,
+                seed,
+                frequency,
+                octaves,
+                lacunarity,
+                persistence,
+            } => match basis {
+                Basis::Perlin => Box::new(
+                    Fbm::<Perlin>::new(seeded(seed))
+                        .set_octaves(*octaves)
+                        .set_frequency(*frequency)
+                        .set_lacunarity(*lacunarity)
+                        .set_persistence(*persistence),
+                ),
+                Basis::Simplex => Box::new(
+                    Fbm::<Simplex>::new(seeded(seed))
+                        .set_octaves(*octaves)
+                        .set_frequency(*frequency)
+                        .set_lacunarity(*lacunarity)
+                        .set_persistence(*persistence),
+                ),
+            },
+            NoiseNode::Warp {
+                source,
+                seed,
+                frequency,
+                power,
+                roughness,
+            } => Box::new(
+                Turbulence::<_, Perlin>::new(source.build(world_seed))
+                    .set_seed(seeded(seed))
+                    .set_frequency(*frequency)
+                    .set_power(*power)
+                    .set_roughness(*roughness),
+            ),
+            NoiseNode::Clamp { source, min, max } => {
+                Box::new(Clamp::<f64, _, 3>::new(source.build(world_seed)).set_bounds(*min, *max))
+            }
+            NoiseNode::Select {
+                a,
+                b,
+                control,
+                min,
+                max,
+                falloff,
+            } => Box::new(
+                Select::<f64, _, _, _, 3>::new(
+                    a.build(world_seed),
+                    b.build(world_seed),
+                    control.build(world_seed),
+                )
+                .set_bounds(*min, *max)
+                .set_falloff(*falloff),
+            ),
+            NoiseNode::Add(a, b) => Box::new(Add::<f64, _, _, 3>::new(
+                a.build(world_seed),
+                b.build(world_seed),
+            )),
+            NoiseNode::Multiply(a, b) => Box::new(Multiply::<f64, _, _, 3>::new(
+                a.build(world_seed),
+                b.build(world_seed),
+            )),
+        }
+    }
+}
+
+struct Constant(f64);
+
+impl NoiseFn<f64, 3> for Constant {
+    fn get(&self, _point: [f64; 3]) -> f64 {
+        self.0
+    }
+}
+
+struct Gradient {
+    height: f64,
+    falloff: f64,
+}
+
+impl NoiseFn<f64, 3> for Gradient {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        (self.height - point[1]) * self.falloff
+    }
+}
+
+/// Worley noise on top of the `noise` crate's core function, as `noise::Worley` can't be
+/// shared between threads
+struct Worley {
+    perm_table: PermutationTable,
+    frequency: f64,
+    return_type: ReturnType,
+}
+
+impl NoiseFn<f64, 3> for Worley {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        worley_3d(
+            &self.perm_table,
+            distance_functions::euclidean,
+            self.return_type,
+            point.map(|value| value * self.frequency),
+        )
+    }
+}
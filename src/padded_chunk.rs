@@ -0,0 +1,160 @@
+use bevy::prelude::{IVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::{Chunk, CHUNK_SIZE},
+    chunk_manager::ChunkManager,
+    tint_overrides::TintOverrides,
+    voxel::{BlockTint, Voxel, BLOCK_TINT_COUNT},
+    world_coords::I64Vec3,
+    world_generator::WorldGenerator,
+};
+
+/// Voxels along each side of a padded chunk, the chunk and one voxel of its neighbours
+pub const PADDED_SIZE: usize = CHUNK_SIZE + 2;
+
+/// How voxels of neighbouring chunks that aren't loaded are meshed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissingNeighbours {
+    /// As empty, closing the surface off along the border
+    Air,
+    /// As solid, leaving out the faces along the border
+    Solid,
+    /// Not at all, the chunk isn't meshed until its neighbours are loaded
+    #[default]
+    Defer,
+}
+
+/// Copy of a chunk with the layer of voxels of its 26 neighbours around it, everything
+/// meshing needs to get the borders right without going back to the `ChunkManager`
+#[derive(Clone)]
+pub struct PaddedChunk {
+    /// World position of the chunk's first voxel
+    origin: I64Vec3,
+    voxels: Vec<Voxel>,
+    /// Colours of the block tints for every column, padded like the voxels
+    tints: Vec<[Vec3; BLOCK_TINT_COUNT]>,
+    /// Tints game logic set over the padded chunk, looked up before the columns' climate
+    tint_overrides: TintOverrides,
+}
+
+impl PaddedChunk {
+    /// Snapshot of the chunk and its borders, or None if a neighbour is missing and
+    /// the policy is to defer
+    pub fn new(
+        chunk_manager: &ChunkManager,
+        chunk: &Chunk,
+        chunk_pos: &I64Vec3,
+        missing: MissingNeighbours,
+    ) -> Option<Self> {
+        PaddedChunk::with_neighbours(
+            chunk,
+            chunk_pos,
+            *chunk_pos * CHUNK_SIZE as i64,
+            missing,
+            chunk_manager.generator(),
+            chunk_manager.tint_overrides(),
+            |neighbour_pos| chunk_manager.chunk(neighbour_pos),
+        )
+    }
+
+    /// Like `new`, with the neighbours looked up by `lookup` instead of in the
+    /// `ChunkManager`, for grids of chunks of their own. `origin` is the world position the
+    /// textures and tints are picked for
+    pub fn with_neighbours<'a>(
+        chunk: &'a Chunk,
+        chunk_pos: &I64Vec3,
+        origin: I64Vec3,
+        missing: MissingNeighbours,
+        generator: &WorldGenerator,
+        tint_overrides: &TintOverrides,
+        lookup: impl Fn(&I64Vec3) -> Option<&'a Chunk>,
+    ) -> Option<Self> {
+        // Look every neighbour up once, indexed by the offset to it plus one on each axis
+        let mut neighbours = [None; 27];
+        for (index, neighbour) in neighbours.iter_mut().enumerate() {
+            let offset = neighbour_offset(index);
+            *neighbour = if offset == IVec3::ZERO {
+                Some(chunk)
+            } else {
+                let neighbour = lookup(&(*chunk_pos + offset));
+                if neighbour.is_none() && missing == MissingNeighbours::Defer {
+                    return None;
+                }
+                neighbour
+            };
+        }
+
+        let missing_voxel = match missing {
+            MissingNeighbours::Solid => Voxel::new(true),
+            _ => Voxel::new_empty(),
+        };
+        let chunk_size = CHUNK_SIZE as i32;
+        let mut voxels = vec![missing_voxel; PADDED_SIZE * PADDED_SIZE * PADDED_SIZE];
+        for x in -1..=chunk_size {
+            for y in -1..=chunk_size {
+                for z in -1..=chunk_size {
+                    let voxel_pos = IVec3::new(x, y, z);
+                    let (offset, local_pos) =
+                        ChunkManager::world_to_chunk_coords(&voxel_pos.into());
+                    let Some(neighbour) = neighbours[neighbour_index(offset.as_ivec3())] else { continue; };
+                    voxels[padded_index(voxel_pos)] =
+                        neighbour.voxels[Chunk::get_index(&local_pos)];
+                }
+            }
+        }
+
+        let mut tints = Vec::with_capacity(PADDED_SIZE * PADDED_SIZE);
+        for x in -1..=chunk_size {
+            for z in -1..=chunk_size {
+                let (world_x, world_z) = (origin.x + x as i64, origin.z + z as i64);
+                tints.push(
+                    [BlockTint::Grass, BlockTint::Water]
+                        .map(|tint| generator.tint_color(tint, world_x, world_z)),
+                );
+            }
+        }
+        let tint_overrides =
+            tint_overrides.within(origin - IVec3::ONE, origin + IVec3::splat(chunk_size));
+        Some(Self {
+            origin,
+            voxels,
+            tints,
+            tint_overrides,
+        })
+    }
+
+    /// The voxel at the position relative to the chunk, from -1 to `CHUNK_SIZE` on each axis
+    pub fn get(&self, voxel_pos: IVec3) -> &Voxel {
+        &self.voxels[padded_index(voxel_pos)]
+    }
+
+    pub fn origin(&self) -> I64Vec3 {
+        self.origin
+    }
+
+    /// Colour of the tint at the voxel position, with the same range as `get`. An override
+    /// covering the voxel takes the place of the column's climate
+    pub fn tint_color(&self, tint: BlockTint, voxel_pos: IVec3) -> Vec3 {
+        if let Some(color) = self.tint_overrides.color(tint, self.origin + voxel_pos) {
+            return color;
+        }
+        let column = (voxel_pos + IVec3::ONE).as_uvec3();
+        self.tints[column.x as usize * PADDED_SIZE + column.z as usize][tint as usize]
+    }
+}
+
+fn padded_index(voxel_pos: IVec3) -> usize {
+    let pos = (voxel_pos + IVec3::ONE).as_uvec3();
+    (pos.x as usize * PADDED_SIZE + pos.y as usize) * PADDED_SIZE + pos.z as usize
+}
+
+fn neighbour_index(offset: IVec3) -> usize {
+    let index = (offset + IVec3::ONE).as_uvec3();
+    (index.x * 9 + index.y * 3 + index.z) as usize
+}
+
+/// Offset to the chunk at `index` of the 27 around a chunk, the chunk itself included
+pub fn neighbour_offset(index: usize) -> IVec3 {
+    IVec3::new(index as i32 / 9, index as i32 / 3 % 3, index as i32 % 3) - IVec3::ONE
+}
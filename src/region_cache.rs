@@ -0,0 +1,66 @@
+use std::{collections::VecDeque, hash::Hash};
+
+use bevy::utils::HashMap;
+
+use crate::world_coords::I64Vec2;
+
+/// Values worked out from the world, kept in square regions of columns so the ones the
+/// player left behind can be dropped. Once there are more than `max_regions` regions, the
+/// one used longest ago goes first
+pub struct RegionCache<K, V> {
+    region_size: i64,
+    max_regions: usize,
+    regions: HashMap<I64Vec2, HashMap<K, V>>,
+    /// Regions from the one used longest ago to the latest
+    recent: VecDeque<I64Vec2>,
+}
+
+impl<K: Eq + Hash, V: Copy> RegionCache<K, V> {
+    pub fn new(region_size: i64, max_regions: usize) -> Self {
+        Self {
+            region_size,
+            max_regions,
+            regions: HashMap::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// The value of the key, kept in the region of the column
+    pub fn get(&mut self, column: I64Vec2, key: &K) -> Option<V> {
+        let region = column.div_euclid(self.region_size);
+        let value = self.regions.get(&region)?.get(key).copied();
+        self.touch(region);
+        value
+    }
+
+    /// Keep the value, unless the key is already known. Returns the one kept
+    pub fn insert(&mut self, column: I64Vec2, key: K, value: V) -> V {
+        let region = column.div_euclid(self.region_size);
+        let kept = *self
+            .regions
+            .entry(region)
+            .or_default()
+            .entry(key)
+            .or_insert(value);
+        self.touch(region);
+        while self.recent.len() > self.max_regions {
+            if let Some(oldest) = self.recent.pop_front() {
+                self.regions.remove(&oldest);
+            }
+        }
+        kept
+    }
+
+    pub fn clear(&mut self) {
+        self.regions.clear();
+        self.recent.clear();
+    }
+
+    fn touch(&mut self, region: I64Vec2) {
+        if self.recent.back() == Some(&region) {
+            return;
+        }
+        self.recent.retain(|recent| *recent != region);
+        self.recent.push_back(region);
+    }
+}
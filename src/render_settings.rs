@@ -0,0 +1,92 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk_mesh_builder::{ChunkVertexFormat, MeshingMode},
+    chunk_transition::ChunkTransition,
+    file_watcher::FileWatcher,
+    padded_chunk::MissingNeighbours,
+};
+
+pub const RENDER_SETTINGS_PATH: &str = "assets/render.ron";
+
+/// Reads the settings of how the world is drawn from `RENDER_SETTINGS_PATH`, and reloads
+/// them whenever the file is saved. Unlike the `WorldGenSettings`, changing them leaves
+/// the world as it is, the chunks are only meshed again
+pub struct RenderSettingsPlugin;
+
+impl Plugin for RenderSettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RenderSettings::load_or_default(RENDER_SETTINGS_PATH))
+            .insert_resource(RenderSettingsWatcher(FileWatcher::new(
+                RENDER_SETTINGS_PATH,
+            )))
+            .add_system(watch_settings_file);
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderSettingsError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// How the chunks are meshed, as blocks or as a smooth surface
+    pub meshing: MeshingMode,
+    /// How the vertices of blocky meshes are stored, as floats or packed into two u32s
+    pub vertex_format: ChunkVertexFormat,
+    /// How chunks are meshed next to neighbours that aren't loaded yet
+    pub missing_neighbours: MissingNeighbours,
+    /// How chunks appear once they're meshed
+    pub chunk_transition: ChunkTransition,
+    /// Fog closing in at the render distance to hide chunks that are still loading. The far
+    /// terrain beyond them isn't fogged
+    pub distance_fog: bool,
+}
+
+impl RenderSettings {
+    pub fn load(path: &str) -> Result<Self, RenderSettingsError> {
+        let contents = fs::read_to_string(path).map_err(RenderSettingsError::Io)?;
+        ron::from_str(&contents).map_err(RenderSettingsError::Parse)
+    }
+
+    pub fn load_or_default(path: &str) -> Self {
+        match RenderSettings::load(path) {
+            Ok(settings) => settings,
+            Err(error) => {
+                println!("Using default render settings, failed to load {path}: {error:?}");
+                RenderSettings::default()
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct RenderSettingsWatcher(pub FileWatcher);
+
+fn watch_settings_file(
+    time: Res<Time>,
+    mut watcher: ResMut<RenderSettingsWatcher>,
+    mut settings: ResMut<RenderSettings>,
+) {
+    if !watcher.0.changed(time.delta()) {
+        return;
+    }
+
+    let path = &watcher.0.path;
+    match RenderSettings::load(path) {
+        // Only touch the resource on an actual change, as that meshes the chunks again
+        Ok(new_settings) => {
+            if *settings != new_settings {
+                println!("Reloaded render settings from {path}");
+                *settings = new_settings;
+            }
+        }
+        Err(error) => println!("Failed to reload {path}: {error:?}"),
+    }
+}
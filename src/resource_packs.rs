@@ -0,0 +1,307 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{prelude::*, tasks::IoTaskPool, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_materials::BlockMaterials,
+    chunk_manager::ChunkManager,
+    file_watcher::FileWatcher,
+    voxel::VoxelType,
+    voxel_material::VoxelMaterial,
+    voxel_textures::{BlockModel, BlockTextures, TEXTURE_HEIGHT, TEXTURE_WIDTH},
+};
+
+pub const RESOURCE_PACKS_PATH: &str = "assets/resource_packs.ron";
+/// Directory of the packs, within the assets directory
+pub const PACKS_DIR: &str = "packs";
+pub const SPRITESHEET_FILE: &str = "spritesheet.png";
+/// Maps laid out like the spritesheet, see `VoxelMaterial`
+pub const NORMAL_MAP_FILE: &str = "spritesheet_normal.png";
+pub const SURFACE_MAP_FILE: &str = "spritesheet_surface.png";
+pub const PACK_FILE: &str = "pack.ron";
+const ASSETS_DIR: &str = "assets";
+
+/// The surface of the built-in chunk materials, leaving the surface map as it is
+pub const DEFAULT_MATERIAL: MaterialParameters = MaterialParameters {
+    perceptual_roughness: Some(1.0),
+    metallic: Some(1.0),
+    reflectance: Some(0.125),
+};
+
+/// Reads the list of resource packs from `RESOURCE_PACKS_PATH`, and applies them to the
+/// chunks whenever the file is saved
+pub struct ResourcePacksPlugin;
+
+impl Plugin for ResourcePacksPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ResourcePacks::load_or_default(RESOURCE_PACKS_PATH))
+            .insert_resource(ResourcePacksWatcher(FileWatcher::new(RESOURCE_PACKS_PATH)))
+            .init_resource::<ResolvingPacks>()
+            .add_systems(
+                (
+                    watch_resource_packs_file,
+                    resolve_resource_packs,
+                    apply_resource_packs,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Debug)]
+pub enum ResourcePackError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+/// Packs in `PACKS_DIR` laid over the built-in assets in order, so later packs override the
+/// textures, block models and material parameters of earlier ones. A pack directory may hold
+/// a `SPRITESHEET_FILE`, `NORMAL_MAP_FILE` and `SURFACE_MAP_FILE` with the layout of the
+/// built-in ones, and a `PACK_FILE`
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourcePacks {
+    pub packs: Vec<String>,
+}
+
+/// Contents of a `PACK_FILE`, everything being optional
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourcePack {
+    pub material: MaterialParameters,
+    /// Block models by block name
+    pub blocks: HashMap<String, BlockModel>,
+}
+
+/// Surface of the chunk materials, the roughness and metallic scaling those of the surface
+/// map. Fields left out keep the value of the packs before
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialParameters {
+    pub perceptual_roughness: Option<f32>,
+    pub metallic: Option<f32>,
+    pub reflectance: Option<f32>,
+}
+
+impl MaterialParameters {
+    /// These parameters with the ones given by `other` replaced
+    pub fn merged(&self, other: &MaterialParameters) -> MaterialParameters {
+        MaterialParameters {
+            perceptual_roughness: other.perceptual_roughness.or(self.perceptual_roughness),
+            metallic: other.metallic.or(self.metallic),
+            reflectance: other.reflectance.or(self.reflectance),
+        }
+    }
+}
+
+/// What the listed packs add up to
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedPacks {
+    /// Asset paths of the spritesheet and maps, each from the last pack that has one
+    pub spritesheet_path: String,
+    pub normal_map_path: String,
+    pub surface_map_path: String,
+    pub material: MaterialParameters,
+    pub block_textures: BlockTextures,
+}
+
+impl ResourcePacks {
+    pub fn load(path: &str) -> Result<Self, ResourcePackError> {
+        let contents = fs::read_to_string(path).map_err(ResourcePackError::Io)?;
+        ron::from_str(&contents).map_err(ResourcePackError::Parse)
+    }
+
+    pub fn load_or_default(path: &str) -> Self {
+        match ResourcePacks::load(path) {
+            Ok(packs) => packs,
+            Err(error) => {
+                println!("Using no resource packs, failed to load {path}: {error:?}");
+                ResourcePacks::default()
+            }
+        }
+    }
+
+    /// Lay the packs over the built-in assets. Packs that are missing or fail to load are
+    /// skipped, and so are images that aren't the size of the built-in spritesheet and
+    /// block models with sprites outside of it
+    pub fn resolve(&self) -> ResolvedPacks {
+        let mut resolved = ResolvedPacks {
+            spritesheet_path: SPRITESHEET_FILE.to_string(),
+            normal_map_path: NORMAL_MAP_FILE.to_string(),
+            surface_map_path: SURFACE_MAP_FILE.to_string(),
+            material: DEFAULT_MATERIAL,
+            block_textures: BlockTextures::default(),
+        };
+        for name in self.packs.iter() {
+            let pack_dir = format!("{PACKS_DIR}/{name}");
+            if !Path::new(ASSETS_DIR).join(&pack_dir).is_dir() {
+                println!("Skipping resource pack {name}, there's no {ASSETS_DIR}/{pack_dir}");
+                continue;
+            }
+
+            for (file, resolved_path) in [
+                (SPRITESHEET_FILE, &mut resolved.spritesheet_path),
+                (NORMAL_MAP_FILE, &mut resolved.normal_map_path),
+                (SURFACE_MAP_FILE, &mut resolved.surface_map_path),
+            ] {
+                let path = format!("{pack_dir}/{file}");
+                let full_path = Path::new(ASSETS_DIR).join(&path);
+                if !full_path.is_file() {
+                    continue;
+                }
+                // Sprites are found by their place in the spritesheet, other sizes would
+                // put them elsewhere or cut them off
+                let size = (TEXTURE_WIDTH as u32, TEXTURE_HEIGHT as u32);
+                match image::image_dimensions(&full_path) {
+                    Ok(dimensions) if dimensions == size => *resolved_path = path,
+                    Ok((width, height)) => println!(
+                        "Skipping {ASSETS_DIR}/{path}, it's {width}x{height} instead of {}x{}",
+                        size.0, size.1
+                    ),
+                    Err(error) => println!("Skipping {ASSETS_DIR}/{path}: {error:?}"),
+                }
+            }
+
+            let pack_path = format!("{ASSETS_DIR}/{pack_dir}/{PACK_FILE}");
+            if !Path::new(&pack_path).is_file() {
+                continue;
+            }
+            let pack = match ResourcePack::load(&pack_path) {
+                Ok(pack) => pack,
+                Err(error) => {
+                    println!("Skipping {pack_path}: {error:?}");
+                    continue;
+                }
+            };
+            resolved.material = resolved.material.merged(&pack.material);
+            for (block_name, model) in pack.blocks.iter() {
+                let Some(voxel_type) = VoxelType::from_name(block_name) else {
+                    println!("Unknown block \"{block_name}\" in {pack_path}");
+                    continue;
+                };
+                if let Err(sprite) = resolved.block_textures.add_model(voxel_type, model) {
+                    println!(
+                        "Skipping block \"{block_name}\" in {pack_path}, its sprite {sprite} is outside of the spritesheet"
+                    );
+                }
+            }
+        }
+        resolved
+    }
+}
+
+impl ResourcePack {
+    pub fn load(path: &str) -> Result<Self, ResourcePackError> {
+        let contents = fs::read_to_string(path).map_err(ResourcePackError::Io)?;
+        ron::from_str(&contents).map_err(ResourcePackError::Parse)
+    }
+}
+
+#[derive(Resource)]
+pub struct ResourcePacksWatcher(pub FileWatcher);
+
+/// Packs being resolved off the main thread, as that reads every pack's directory and
+/// `PACK_FILE`
+#[derive(Resource, Default)]
+pub struct ResolvingPacks {
+    /// Counts up on every change of the `ResourcePacks`, so packs resolved for an older list
+    /// are dropped
+    generation: u32,
+    /// Packs resolved by the tasks, with the generation they were resolved for
+    resolved: Arc<Mutex<Vec<(u32, ResolvedPacks)>>>,
+}
+
+fn watch_resource_packs_file(
+    time: Res<Time>,
+    mut watcher: ResMut<ResourcePacksWatcher>,
+    mut resource_packs: ResMut<ResourcePacks>,
+) {
+    if !watcher.0.changed(time.delta()) {
+        return;
+    }
+
+    let path = &watcher.0.path;
+    match ResourcePacks::load(path) {
+        Ok(new_packs) => {
+            if *resource_packs != new_packs {
+                println!("Reloaded resource packs from {path}");
+                *resource_packs = new_packs;
+            }
+        }
+        Err(error) => println!("Failed to reload {path}: {error:?}"),
+    }
+}
+
+/// Start resolving the packs whenever the list changes
+fn resolve_resource_packs(
+    resource_packs: Res<ResourcePacks>,
+    mut resolving: ResMut<ResolvingPacks>,
+) {
+    if !resource_packs.is_changed() {
+        return;
+    }
+    resolving.generation = resolving.generation.wrapping_add(1);
+    let generation = resolving.generation;
+    let resource_packs = resource_packs.clone();
+    let resolved = resolving.resolved.clone();
+    IoTaskPool::get()
+        .spawn(async move {
+            let packs = resource_packs.resolve();
+            resolved.lock().unwrap().push((generation, packs));
+        })
+        .detach();
+}
+
+/// Switch the chunks over to the textures, block models and materials of the packs, once
+/// they're resolved
+fn apply_resource_packs(
+    resolving: Res<ResolvingPacks>,
+    asset_server: Res<AssetServer>,
+    mut chunk_manager: ResMut<ChunkManager>,
+    block_materials: Res<BlockMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut voxel_materials: ResMut<Assets<VoxelMaterial>>,
+) {
+    let resolved = std::mem::take(&mut *resolving.resolved.lock().unwrap());
+    let current = resolved
+        .into_iter()
+        .find(|(generation, _)| *generation == resolving.generation);
+    let Some((_, resolved)) = current else { return; };
+    let spritesheet_handle: Handle<Image> = asset_server.load(&resolved.spritesheet_path);
+    let normal_map_handle: Handle<Image> = asset_server.load(&resolved.normal_map_path);
+    let surface_map_handle: Handle<Image> = asset_server.load(&resolved.surface_map_path);
+    let [perceptual_roughness, metallic, reflectance] = [
+        resolved.material.perceptual_roughness,
+        resolved.material.metallic,
+        resolved.material.reflectance,
+    ]
+    .map(Option::unwrap_or_default);
+
+    for (standard, packed) in block_materials.spritesheet_materials() {
+        if let Some(material) = materials.get_mut(standard) {
+            material.base_color_texture = Some(spritesheet_handle.clone());
+            material.normal_map_texture = Some(normal_map_handle.clone());
+            material.metallic_roughness_texture = Some(surface_map_handle.clone());
+            material.occlusion_texture = Some(surface_map_handle.clone());
+            material.perceptual_roughness = perceptual_roughness;
+            material.metallic = metallic;
+            material.reflectance = reflectance;
+        }
+        let Some(material) = voxel_materials.get_mut(packed) else { continue; };
+        material.spritesheet = spritesheet_handle.clone();
+        material.normal_map = normal_map_handle.clone();
+        material.surface_map = surface_map_handle.clone();
+        // The alpha cutoff in w stays
+        material.surface.x = perceptual_roughness;
+        material.surface.y = metallic;
+        material.surface.z = reflectance;
+    }
+    chunk_manager.spritesheet_handle = spritesheet_handle;
+    chunk_manager.normal_map_handle = normal_map_handle;
+    chunk_manager.surface_map_handle = surface_map_handle;
+    chunk_manager.set_block_textures(resolved.block_textures);
+}
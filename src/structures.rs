@@ -0,0 +1,448 @@
+use std::{collections::HashMap, ffi::OsStr, fs, path::Path};
+
+use bevy::prelude::{IVec2, IVec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::{
+    chunk::{Chunk, CHUNK_SIZE},
+    region_cache::RegionCache,
+    voxel::{Voxel, VoxelType},
+    world_coords::{I64Vec2, I64Vec3},
+    world_generator::WorldGenerator,
+};
+
+pub const STRUCTURES_PATH: &str = "assets/structures";
+/// Side of the square regions of columns the anchor cache is kept in
+pub const ANCHOR_REGION_SIZE: i64 = 256;
+/// Most regions the anchor cache keeps, the one used longest ago is dropped first
+pub const MAX_ANCHOR_REGIONS: usize = 32;
+
+#[derive(Debug)]
+pub enum StructureError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    UnknownBlock(String),
+    UnknownPaletteKey(char),
+    Invalid(String),
+}
+
+/// Where in the terrain a structure may be placed
+#[derive(Clone, Debug, Deserialize)]
+pub enum PlacementRule {
+    /// The anchor sits on the first air voxel above the ground, never under water
+    OnSurface,
+    /// The anchor is buried this many voxels below the ground
+    Underground { min_depth: i32, max_depth: i32 },
+}
+
+/// The world is split into square cells `spacing` voxels wide, each holding at most one
+/// of the structure. Anchors are kept more than `min_spacing` voxels apart along x and z.
+/// Structures of different templates don't overlap along x and z either, the template
+/// earlier in the list wins
+#[derive(Clone, Debug, Deserialize)]
+pub struct Placement {
+    pub rule: PlacementRule,
+    pub spacing: i32,
+    #[serde(default)]
+    pub min_spacing: i32,
+    /// Chance for a cell to get a structure at all
+    #[serde(default = "default_chance")]
+    pub chance: f64,
+}
+
+fn default_chance() -> f64 {
+    1.0
+}
+
+/// A structure as written in the RON files. Layers go from the bottom up, each layer a list
+/// of rows along z, and each row a string of palette keys along x. Keys missing from the
+/// palette, like spaces, keep whatever the terrain has there
+#[derive(Deserialize)]
+struct StructureFile {
+    palette: HashMap<char, String>,
+    layers: Vec<Vec<String>>,
+    anchor: (i32, i32, i32),
+    placement: Placement,
+}
+
+pub struct StructureTemplate {
+    pub name: String,
+    pub placement: Placement,
+    anchor: IVec3,
+    voxels: Vec<(IVec3, Voxel)>,
+}
+
+impl StructureTemplate {
+    pub fn load(path: &Path) -> Result<Self, StructureError> {
+        let contents = fs::read_to_string(path).map_err(StructureError::Io)?;
+        let file: StructureFile = ron::from_str(&contents).map_err(StructureError::Parse)?;
+
+        let mut palette = HashMap::new();
+        for (key, name) in file.palette.iter() {
+            let voxel_type = VoxelType::from_name(name)
+                .ok_or_else(|| StructureError::UnknownBlock(name.clone()))?;
+            palette.insert(*key, Voxel::from_type(voxel_type));
+        }
+
+        let mut voxels = Vec::new();
+        for (y, layer) in file.layers.iter().enumerate() {
+            for (z, row) in layer.iter().enumerate() {
+                for (x, key) in row.chars().enumerate() {
+                    if let Some(voxel) = palette.get(&key) {
+                        voxels.push((IVec3::new(x as i32, y as i32, z as i32), *voxel));
+                    } else if !key.is_whitespace() {
+                        return Err(StructureError::UnknownPaletteKey(key));
+                    }
+                }
+            }
+        }
+
+        if file.placement.spacing <= file.placement.min_spacing.max(0) {
+            return Err(StructureError::Invalid(
+                "spacing has to be larger than min_spacing".to_string(),
+            ));
+        }
+
+        Ok(StructureTemplate {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            placement: file.placement,
+            anchor: IVec3::new(file.anchor.0, file.anchor.1, file.anchor.2),
+            voxels,
+        })
+    }
+
+    /// Nearest and farthest voxels from the anchor, None if there are no voxels
+    fn reach(&self) -> Option<(IVec3, IVec3)> {
+        let offsets = self.voxels.iter().map(|(offset, _)| *offset - self.anchor);
+        offsets.fold(None, |reach, offset| match reach {
+            Some((min, max)) => Some((offset.min(min), offset.max(max))),
+            None => Some((offset, offset)),
+        })
+    }
+
+    /// Columns the template covers when anchored in the given column, None if it has no voxels
+    fn footprint(&self, column: I64Vec2) -> Option<(I64Vec2, I64Vec2)> {
+        let (reach_min, reach_max) = self.reach()?;
+        Some((
+            column + IVec2::new(reach_min.x, reach_min.z),
+            column + IVec2::new(reach_max.x, reach_max.z),
+        ))
+    }
+
+    /// Load every `.ron` file in the directory, skipping the ones that fail
+    pub fn load_dir(path: &str) -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(path) else { return Vec::new(); };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension() == Some(OsStr::new("ron")))
+            .collect();
+        // Keep the order stable, as it's part of the placement seeds
+        paths.sort();
+
+        paths
+            .iter()
+            .filter_map(|path| match StructureTemplate::load(path) {
+                Ok(template) => Some(template),
+                Err(error) => {
+                    println!("Failed to load structure {}: {:?}", path.display(), error);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Mix the world seed, template and placement cell into a seed for that cell. Cells within
+/// reach of an i32 only mix in the low half of their coordinates, so the world keeps its
+/// structures where it's always had them
+fn cell_seed(seed: u32, template_index: usize, cell_x: i64, cell_z: i64) -> u64 {
+    let mut hash = (seed as u64) ^ ((template_index as u64) << 32);
+    for value in [cell_x, cell_z] {
+        let high = i32::try_from(value)
+            .is_err()
+            .then_some((value >> 32) as u32 as u64);
+        for half in [Some(value as u32 as u64), high].into_iter().flatten() {
+            hash = (hash ^ half).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            hash ^= hash >> 29;
+        }
+    }
+    hash
+}
+
+/// Where the templates are anchored in the cells looked at lately, None for cells without
+/// a structure. Chunks stacked in a column, and the chunks around a structure, all find its
+/// anchor once instead of each looking for the surface again. Dropped cells are found again
+/// the same way, anchors don't depend on the cache
+pub struct StructureAnchors {
+    cache: RegionCache<(usize, I64Vec2), Option<I64Vec3>>,
+}
+
+impl Default for StructureAnchors {
+    fn default() -> Self {
+        Self {
+            cache: RegionCache::new(ANCHOR_REGION_SIZE, MAX_ANCHOR_REGIONS),
+        }
+    }
+}
+
+impl StructureAnchors {
+    /// Forget every anchor, like when the world or the templates change
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    fn anchor(
+        &mut self,
+        templates: &[StructureTemplate],
+        template_index: usize,
+        generator: &WorldGenerator,
+        cell: I64Vec2,
+    ) -> Option<I64Vec3> {
+        let column = cell * templates[template_index].placement.spacing as i64;
+        let key = (template_index, cell);
+        if let Some(anchor) = self.cache.get(column, &key) {
+            return anchor;
+        }
+        let anchor = find_anchor(templates, template_index, generator, cell);
+        self.cache.insert(column, key, anchor)
+    }
+}
+
+/// Voxels, in world coordinates, of every structure anchored from `min` to `max`, inclusive.
+/// They may reach outside of it
+pub fn place_structures(
+    templates: &[StructureTemplate],
+    generator: &WorldGenerator,
+    anchors: &mut StructureAnchors,
+    min: I64Vec3,
+    max: I64Vec3,
+) -> Vec<(I64Vec3, Voxel)> {
+    let mut edits = Vec::new();
+    for template_index in 0..templates.len() {
+        place_template(
+            templates,
+            template_index,
+            generator,
+            anchors,
+            min,
+            max,
+            &mut edits,
+        );
+    }
+    edits
+}
+
+/// Voxels of the structures reaching into the chunk, wherever they're anchored, by their
+/// index within the chunk. Every chunk takes in its part of the structures when it's
+/// generated, so structures never write into chunks that are already loaded
+pub fn chunk_structure_voxels(
+    templates: &[StructureTemplate],
+    generator: &WorldGenerator,
+    anchors: &mut StructureAnchors,
+    chunk_pos: I64Vec3,
+) -> Vec<(usize, Voxel)> {
+    let chunk_min = chunk_pos * CHUNK_SIZE as i64;
+    let chunk_max = chunk_min + IVec3::splat(CHUNK_SIZE as i32 - 1);
+
+    let mut edits = Vec::new();
+    for (template_index, template) in templates.iter().enumerate() {
+        let Some((reach_min, reach_max)) = template.reach() else { continue; };
+        let (min, max) = (chunk_min - reach_max, chunk_max - reach_min);
+        place_template(
+            templates,
+            template_index,
+            generator,
+            anchors,
+            min,
+            max,
+            &mut edits,
+        );
+    }
+    edits
+        .into_iter()
+        .filter(|(world_pos, _)| {
+            world_pos.cmpge(chunk_min).all() && world_pos.cmple(chunk_max).all()
+        })
+        .map(|(world_pos, voxel)| (Chunk::get_index(&(world_pos - chunk_min).as_ivec3()), voxel))
+        .collect()
+}
+
+/// Place the template in every one of its cells where it's anchored from `min` to `max`
+fn place_template(
+    templates: &[StructureTemplate],
+    template_index: usize,
+    generator: &WorldGenerator,
+    anchors: &mut StructureAnchors,
+    min: I64Vec3,
+    max: I64Vec3,
+    edits: &mut Vec<(I64Vec3, Voxel)>,
+) {
+    let template = &templates[template_index];
+    let cell_size = template.placement.spacing as i64;
+    for cell_x in min.x.div_euclid(cell_size)..=max.x.div_euclid(cell_size) {
+        for cell_z in min.z.div_euclid(cell_size)..=max.z.div_euclid(cell_size) {
+            let cell = I64Vec2::new(cell_x, cell_z);
+            let Some(anchor) = anchors.anchor(templates, template_index, generator, cell) else { continue; };
+            if !(anchor.cmpge(min).all() && anchor.cmple(max).all()) {
+                continue;
+            }
+
+            let origin = anchor - template.anchor;
+            edits.extend(
+                template
+                    .voxels
+                    .iter()
+                    .map(|(offset, voxel)| (origin + *offset, *voxel)),
+            );
+        }
+    }
+}
+
+/// Column the template may be anchored in within the cell, if the cell gets a structure at
+/// all, along with the cell's random numbers for the rest of its placement
+fn anchor_column(
+    seed: u32,
+    template_index: usize,
+    template: &StructureTemplate,
+    cell: I64Vec2,
+) -> Option<(I64Vec2, StdRng)> {
+    let placement = &template.placement;
+    let mut rng = StdRng::seed_from_u64(cell_seed(seed, template_index, cell.x, cell.y));
+    if rng.gen::<f64>() >= placement.chance {
+        return None;
+    }
+    let offset_range = placement.spacing - placement.min_spacing.max(0);
+    let offset = IVec2::new(
+        rng.gen_range(0..offset_range),
+        rng.gen_range(0..offset_range),
+    );
+    Some((cell * placement.spacing as i64 + offset, rng))
+}
+
+/// Whether a structure of a template earlier in the list may cover any of the columns.
+/// Only their columns are compared, so a structure is left out even where the one it
+/// overlaps finds no ground, keeping this cheap and independent of the terrain
+fn overlaps_earlier_template(
+    templates: &[StructureTemplate],
+    template_index: usize,
+    seed: u32,
+    (min, max): (I64Vec2, I64Vec2),
+) -> bool {
+    templates[..template_index]
+        .iter()
+        .enumerate()
+        .any(|(other_index, other)| {
+            let Some((reach_min, reach_max)) = other.reach() else { return false; };
+            // Anchors of the other template whose footprint reaches the columns
+            let anchor_min = min - IVec2::new(reach_max.x, reach_max.z);
+            let anchor_max = max - IVec2::new(reach_min.x, reach_min.z);
+            let cell_min = anchor_min.div_euclid(other.placement.spacing as i64);
+            let cell_max = anchor_max.div_euclid(other.placement.spacing as i64);
+            (cell_min.x..=cell_max.x).any(|cell_x| {
+                (cell_min.y..=cell_max.y).any(|cell_z| {
+                    let cell = I64Vec2::new(cell_x, cell_z);
+                    anchor_column(seed, other_index, other, cell).is_some_and(|(column, _)| {
+                        column.x >= anchor_min.x
+                            && column.x <= anchor_max.x
+                            && column.y >= anchor_min.y
+                            && column.y <= anchor_max.y
+                    })
+                })
+            })
+        })
+}
+
+/// Where the template is anchored in the cell, None if the cell has no structure
+fn find_anchor(
+    templates: &[StructureTemplate],
+    template_index: usize,
+    generator: &WorldGenerator,
+    cell: I64Vec2,
+) -> Option<I64Vec3> {
+    let template = &templates[template_index];
+    let seed = generator.settings().seed;
+    let (column, mut rng) = anchor_column(seed, template_index, template, cell)?;
+    let footprint = template.footprint(column)?;
+    if overlaps_earlier_template(templates, template_index, seed, footprint) {
+        return None;
+    }
+
+    let surface = generator.surface_height(column.x, column.y)?;
+    let y = match template.placement.rule {
+        PlacementRule::OnSurface => {
+            let above = generator.voxel(&I64Vec3::new(column.x, surface as i64 + 1, column.y));
+            if above.active {
+                return None;
+            }
+            surface + 1
+        }
+        PlacementRule::Underground {
+            min_depth,
+            max_depth,
+        } => surface - rng.gen_range(min_depth..=max_depth.max(min_depth)),
+    };
+    Some(I64Vec3::new(column.x, y as i64, column.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen_settings::{Generator, WorldGenSettings};
+    use bevy::utils::HashSet;
+
+    /// A square slab of the block, anchored at its corner on the surface
+    fn slab(voxel_type: VoxelType, width: i32, spacing: i32) -> StructureTemplate {
+        let mut voxels = Vec::new();
+        for x in 0..width {
+            for z in 0..width {
+                voxels.push((IVec3::new(x, 0, z), Voxel::from_type(voxel_type)));
+            }
+        }
+        StructureTemplate {
+            name: voxel_type.name().to_string(),
+            placement: Placement {
+                rule: PlacementRule::OnSurface,
+                spacing,
+                min_spacing: 0,
+                chance: 1.0,
+            },
+            anchor: IVec3::ZERO,
+            voxels,
+        }
+    }
+
+    #[test]
+    fn structures_of_different_templates_dont_overlap() {
+        let settings = WorldGenSettings {
+            generator: Generator::Flat {
+                layers: "1*stone".parse().unwrap(),
+                base_height: 0,
+            },
+            ..Default::default()
+        };
+        let generator = WorldGenerator::new(&settings);
+        let templates = [slab(VoxelType::Sand, 5, 8), slab(VoxelType::Dirt, 4, 7)];
+        let edits = place_structures(
+            &templates,
+            &generator,
+            &mut StructureAnchors::default(),
+            I64Vec3::new(-200, 0, -200),
+            I64Vec3::new(200, 8, 200),
+        );
+
+        let columns = |voxel_type: VoxelType| -> HashSet<I64Vec2> {
+            edits
+                .iter()
+                .filter(|(_, voxel)| voxel.voxel_type == voxel_type)
+                .map(|(world_pos, _)| world_pos.xz())
+                .collect()
+        };
+        let (sand, dirt) = (columns(VoxelType::Sand), columns(VoxelType::Dirt));
+        assert!(!sand.is_empty() && !dirt.is_empty());
+        assert!(sand.is_disjoint(&dirt));
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+
+use crate::{
+    chunk_manager::ChunkManager,
+    face::Side,
+    voxel_textures::{sprite_cell_rows, ANIMATED_TEXTURES},
+};
+
+/// Plays the `ANIMATED_TEXTURES` by copying their current frames into the spritesheet, and
+/// into the normal and surface maps laid out like it
+pub struct TextureAnimationPlugin;
+
+impl Plugin for TextureAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TextureAnimations>()
+            .add_system(animate_textures);
+    }
+}
+
+/// The frame each of the `ANIMATED_TEXTURES` currently shows in the spritesheet
+#[derive(Resource, Default)]
+pub struct TextureAnimations {
+    /// The spritesheet and maps the frames were copied into
+    image_handles: [Handle<Image>; 3],
+    frames: [Option<usize>; ANIMATED_TEXTURES.len()],
+}
+
+fn animate_textures(
+    time: Res<Time>,
+    chunk_manager: Res<ChunkManager>,
+    mut animations: ResMut<TextureAnimations>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // Another resource pack's images start out without frames
+    let image_handles = [
+        chunk_manager.spritesheet_handle.clone(),
+        chunk_manager.normal_map_handle.clone(),
+        chunk_manager.surface_map_handle.clone(),
+    ];
+    if animations.image_handles != image_handles {
+        animations.image_handles = image_handles;
+        animations.frames = Default::default();
+    }
+
+    let elapsed = time.elapsed_seconds_wrapped();
+    let frames: [usize; ANIMATED_TEXTURES.len()] = std::array::from_fn(|index| {
+        current_frame(ANIMATED_TEXTURES[index].frame_durations, elapsed)
+    });
+    // Only touch the images when a frame changes, every change uploads them again
+    if animations.frames == frames.map(Some) {
+        return;
+    }
+    if images.get(&chunk_manager.spritesheet_handle).is_none() {
+        return;
+    }
+
+    for (index, animation) in ANIMATED_TEXTURES.iter().enumerate() {
+        if animations.frames[index] == Some(frames[index]) {
+            continue;
+        }
+        // Blocks given their own sprite by a resource pack aren't animated
+        let block_textures = chunk_manager.block_textures();
+        if !block_textures.is_built_in(animation.voxel_type, Side::Top) {
+            continue;
+        }
+        let frame = animation.strip + UVec2::X * frames[index] as u32;
+        let sprite = block_textures.sprite(animation.voxel_type, Side::Top);
+        // Maps that are still loading catch up on the next frame
+        for handle in animations.image_handles.iter() {
+            if let Some(image) = images.get_mut(handle) {
+                copy_sprite(&mut image.data, frame, sprite);
+            }
+        }
+        animations.frames[index] = Some(frames[index]);
+    }
+}
+
+/// Index of the frame shown `elapsed` seconds into the looping animation
+fn current_frame(frame_durations: &[f32], elapsed: f32) -> usize {
+    let total: f32 = frame_durations.iter().sum();
+    let mut time = elapsed % total;
+    for (frame, duration) in frame_durations.iter().enumerate() {
+        if time < *duration {
+            return frame;
+        }
+        time -= duration;
+    }
+    frame_durations.len() - 1
+}
+
+/// Copy the pixels of a sprite, padding included, over another one. Rows the image is too
+/// small for are left out
+fn copy_sprite(data: &mut [u8], from: UVec2, to: UVec2) {
+    for (from_row, to_row) in sprite_cell_rows(from).zip(sprite_cell_rows(to)) {
+        if from_row.end <= data.len() && to_row.end <= data.len() {
+            data.copy_within(from_row, to_row.start);
+        }
+    }
+}
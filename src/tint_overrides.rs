@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+
+use crate::{chunk_manager::ChunkManager, voxel::BlockTint, world_coords::I64Vec3};
+
+/// Colour of a tint over a box of world voxel positions, set by game logic in place of the
+/// climate's
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TintOverride {
+    pub tint: BlockTint,
+    /// Corners of the box, both included
+    pub min: I64Vec3,
+    pub max: I64Vec3,
+    pub color: Vec3,
+}
+
+impl TintOverride {
+    /// Override of a single block
+    pub fn block(tint: BlockTint, world_pos: I64Vec3, color: Vec3) -> Self {
+        Self::region(tint, world_pos, world_pos, color)
+    }
+
+    pub fn region(tint: BlockTint, min: I64Vec3, max: I64Vec3, color: Vec3) -> Self {
+        Self {
+            tint,
+            min: min.min(max),
+            max: min.max(max),
+            color,
+        }
+    }
+
+    pub fn contains(&self, world_pos: I64Vec3) -> bool {
+        world_pos.cmpge(self.min).all() && world_pos.cmple(self.max).all()
+    }
+
+    /// Whether the box reaches into the one from `min` to `max`, both included
+    pub fn overlaps(&self, min: I64Vec3, max: I64Vec3) -> bool {
+        self.min.cmple(max).all() && self.max.cmpge(min).all()
+    }
+
+    /// Chunk positions of the chunks the box reaches into
+    fn chunk_positions(&self) -> impl Iterator<Item = I64Vec3> {
+        let (min, _) = ChunkManager::world_to_chunk_coords(&self.min);
+        let (max, _) = ChunkManager::world_to_chunk_coords(&self.max);
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| I64Vec3::new(x, y, z)))
+        })
+    }
+}
+
+/// Tints game logic sets per block or region, consulted before the climate. Later overrides
+/// win where they overlap. Changing them remeshes the chunks they cover
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct TintOverrides {
+    pub overrides: Vec<TintOverride>,
+}
+
+impl TintOverrides {
+    /// Colour of the tint at the world position, if an override covers it
+    pub fn color(&self, tint: BlockTint, world_pos: I64Vec3) -> Option<Vec3> {
+        self.overrides
+            .iter()
+            .rev()
+            .find(|tint_override| tint_override.tint == tint && tint_override.contains(world_pos))
+            .map(|tint_override| tint_override.color)
+    }
+
+    /// The overrides reaching into the box from `min` to `max`, both included
+    pub fn within(&self, min: I64Vec3, max: I64Vec3) -> Self {
+        Self {
+            overrides: self
+                .overrides
+                .iter()
+                .filter(|tint_override| tint_override.overlaps(min, max))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Chunk positions of the chunks whose tints differ between the two sets of overrides.
+    /// Meshes look tints up a voxel past their chunk, so the boxes are padded by one
+    pub fn changed_chunks(&self, other: &TintOverrides) -> Vec<I64Vec3> {
+        let mut chunks = Vec::new();
+        let changed = self
+            .overrides
+            .iter()
+            .filter(|tint_override| !other.overrides.contains(tint_override))
+            .chain(
+                other
+                    .overrides
+                    .iter()
+                    .filter(|tint_override| !self.overrides.contains(tint_override)),
+            );
+        for tint_override in changed {
+            let padded = TintOverride {
+                min: tint_override.min - IVec3::ONE,
+                max: tint_override.max + IVec3::ONE,
+                ..*tint_override
+            };
+            chunks.extend(padded.chunk_positions());
+        }
+        chunks.sort();
+        chunks.dedup();
+        chunks
+    }
+}
+
+pub struct TintOverridesPlugin;
+
+impl Plugin for TintOverridesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TintOverrides>()
+            .add_system(apply_tint_overrides);
+    }
+}
+
+fn apply_tint_overrides(
+    tint_overrides: Res<TintOverrides>,
+    mut chunk_manager: ResMut<ChunkManager>,
+) {
+    if tint_overrides.is_changed() {
+        chunk_manager.set_tint_overrides(tint_overrides.clone());
+    }
+}
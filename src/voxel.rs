@@ -1,15 +1,162 @@
-#[derive(Copy, Clone, Debug)]
+use bevy::prelude::Vec3;
+
+use crate::face::Side;
+
+/// Fluid level of a source block, flowing fluid has lower levels
+pub const FLUID_SOURCE_LEVEL: u8 = 8;
+
+/// Colour a block face is multiplied with, so one texture can look different from place to
+/// place. See `WorldGenerator::tint_color`, and `TintOverrides` for game logic setting it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockTint {
+    Grass = 0,
+    Water,
+}
+
+pub const BLOCK_TINT_COUNT: usize = 2;
+
+/// Id of a material block faces are drawn with, registered in the `BlockMaterials`. Chunks
+/// get a submesh for each material their blocks use, see `chunk_mesh_builder::build_mesh`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockMaterial(pub u16);
+
+impl BlockMaterial {
+    pub const OPAQUE: BlockMaterial = BlockMaterial(0);
+    /// Pixels of the sprite below half alpha are left out, for foliage and the like
+    pub const CUTOUT: BlockMaterial = BlockMaterial(1);
+    /// Blended with what's behind by the alpha of the sprite
+    pub const TRANSLUCENT: BlockMaterial = BlockMaterial(2);
+    /// Opaque, and glowing through the emissive map
+    pub const EMISSIVE: BlockMaterial = BlockMaterial(3);
+
+    /// The materials drawn with the spritesheet that are always registered, by id
+    pub const BUILT_IN: [BlockMaterial; 4] = [
+        BlockMaterial::OPAQUE,
+        BlockMaterial::CUTOUT,
+        BlockMaterial::TRANSLUCENT,
+        BlockMaterial::EMISSIVE,
+    ];
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum VoxelType {
     Default = 0,
     None,
     Dirt,
     Grass,
+    Sand,
+    Water,
+    Stone,
+    Lava,
+    Bedrock,
 }
 
+impl VoxelType {
+    pub const ALL: [VoxelType; 9] = [
+        VoxelType::Default,
+        VoxelType::None,
+        VoxelType::Dirt,
+        VoxelType::Grass,
+        VoxelType::Sand,
+        VoxelType::Water,
+        VoxelType::Stone,
+        VoxelType::Lava,
+        VoxelType::Bedrock,
+    ];
+
+    pub fn is_fluid(&self) -> bool {
+        matches!(self, VoxelType::Water | VoxelType::Lava)
+    }
+
+    /// The tint of the block's side, if it has one
+    pub fn tint(&self, side: Side) -> Option<BlockTint> {
+        match (self, side) {
+            (VoxelType::Grass, Side::Top) => Some(BlockTint::Grass),
+            (VoxelType::Water, _) => Some(BlockTint::Water),
+            _ => None,
+        }
+    }
+
+    /// Colour the block glows in, multiplied with its texture. Its faces light themselves,
+    /// but there's no block light in the world data yet to light up what's around them
+    pub fn emissive_color(&self) -> Option<Vec3> {
+        match self {
+            VoxelType::Lava => Some(Vec3::new(1.0, 0.6, 0.35)),
+            _ => None,
+        }
+    }
+
+    /// Built-in material of the block, unless the `BlockMaterials` map it to another
+    pub fn material(&self) -> BlockMaterial {
+        match self {
+            VoxelType::Water => BlockMaterial::TRANSLUCENT,
+            _ if self.emissive_color().is_some() => BlockMaterial::EMISSIVE,
+            _ => BlockMaterial::OPAQUE,
+        }
+    }
+
+    /// Flat colour of the block for things too far away or too small to show its texture,
+    /// like the generation preview and the far terrain
+    pub fn map_color(&self) -> [u8; 3] {
+        match self {
+            VoxelType::Default => [255, 0, 255],
+            VoxelType::None => [0, 0, 0],
+            VoxelType::Dirt => [120, 85, 55],
+            VoxelType::Grass => [90, 160, 60],
+            VoxelType::Sand => [220, 200, 130],
+            VoxelType::Water => [50, 100, 200],
+            VoxelType::Stone => [128, 128, 128],
+            VoxelType::Lava => [230, 90, 20],
+            VoxelType::Bedrock => [40, 40, 40],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VoxelType::Default => "default",
+            VoxelType::None => "air",
+            VoxelType::Dirt => "dirt",
+            VoxelType::Grass => "grass",
+            VoxelType::Sand => "sand",
+            VoxelType::Water => "water",
+            VoxelType::Stone => "stone",
+            VoxelType::Lava => "lava",
+            VoxelType::Bedrock => "bedrock",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<VoxelType> {
+        match name {
+            "default" => Some(VoxelType::Default),
+            "air" => Some(VoxelType::None),
+            "dirt" => Some(VoxelType::Dirt),
+            "grass" => Some(VoxelType::Grass),
+            "sand" => Some(VoxelType::Sand),
+            "water" => Some(VoxelType::Water),
+            "stone" => Some(VoxelType::Stone),
+            "lava" => Some(VoxelType::Lava),
+            "bedrock" => Some(VoxelType::Bedrock),
+            _ => None,
+        }
+    }
+}
+
+/// Density of a completely filled voxel
+pub const FULL_DENSITY: u8 = u8::MAX;
+
 #[derive(Copy, Clone, Debug)]
 pub struct Voxel {
     pub active: bool,
     pub voxel_type: VoxelType,
+    pub fluid_level: u8,
+    /// How much of the voxel is filled with ground, only used by smooth meshing to place the
+    /// surface between voxels. Solid voxels count as at least half full, and fluids and empty
+    /// voxels as at most half
+    pub density: u8,
 }
 
 impl Default for Voxel {
@@ -17,6 +164,8 @@ impl Default for Voxel {
         Self {
             active: false,
             voxel_type: VoxelType::None,
+            fluid_level: 0,
+            density: 0,
         }
     }
 }
@@ -24,8 +173,10 @@ impl Default for Voxel {
 impl Voxel {
     pub fn new(active: bool) -> Self {
         Self {
-            active: active,
+            active,
             voxel_type: VoxelType::Default,
+            fluid_level: 0,
+            density: if active { FULL_DENSITY } else { 0 },
         }
     }
 
@@ -33,6 +184,39 @@ impl Voxel {
         Self {
             active: false,
             voxel_type: VoxelType::None,
+            fluid_level: 0,
+            density: 0,
+        }
+    }
+
+    /// A voxel of the given type, with fluids as full sources
+    pub fn from_type(voxel_type: VoxelType) -> Self {
+        if voxel_type == VoxelType::None {
+            Voxel::new_empty()
+        } else if voxel_type.is_fluid() {
+            Voxel::new_fluid(voxel_type, FLUID_SOURCE_LEVEL)
+        } else {
+            Self {
+                active: true,
+                voxel_type,
+                fluid_level: 0,
+                density: FULL_DENSITY,
+            }
         }
     }
+
+    /// A fluid voxel, which holds no ground
+    pub fn new_fluid(voxel_type: VoxelType, fluid_level: u8) -> Self {
+        Self {
+            active: true,
+            voxel_type,
+            fluid_level,
+            density: 0,
+        }
+    }
+
+    /// Whether the voxel is ground, as opposed to a fluid or empty
+    pub fn is_solid(&self) -> bool {
+        self.active && !self.voxel_type.is_fluid()
+    }
 }
@@ -0,0 +1,248 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    block_materials::{apply_block_materials, BlockMaterials},
+    chunk::{Chunk, CHUNK_SIZE},
+    chunk_manager::{ChunkManager, RenderedChunk},
+    chunk_mesh_builder::{MeshLod, NEIGHBOUR_OFFSETS},
+    padded_chunk::{MissingNeighbours, PaddedChunk},
+    render_settings::RenderSettings,
+    voxel::Voxel,
+    voxel_engine::{apply_render_settings, apply_world_gen_settings},
+    world_coords::I64Vec3,
+    world_gen_settings::WorldGenSettings,
+};
+
+/// Largest region, in voxels along each axis, that can be cut out into a voxel body
+pub const MAX_VOXEL_BODY_SIZE: i32 = CHUNK_SIZE as i32 * 2;
+
+/// Free-moving voxel objects, like ships and vehicles, each with a grid of chunks of its own
+/// that moves along with it. They're meshed like the world's chunks and simulated as Rapier
+/// rigid bodies
+pub struct VoxelBodyPlugin;
+
+impl Plugin for VoxelBodyPlugin {
+    fn build(&self, app: &mut App) {
+        // After the chunk manager takes in new settings and materials, so bodies are meshed
+        // with them the same frame
+        app.add_system(
+            mesh_voxel_bodies
+                .after(apply_world_gen_settings)
+                .after(apply_render_settings)
+                .after(apply_block_materials),
+        );
+    }
+}
+
+/// Voxels of a free-moving body, in chunks around the entity's origin. Edits are meshed,
+/// and the body given a new collider, by `mesh_voxel_bodies`
+#[derive(Component)]
+pub struct VoxelBody {
+    chunks: HashMap<I64Vec3, Chunk>,
+    /// World position the body's voxel at the origin was cut from, so its blocks keep the
+    /// textures and tints they had in the world
+    source_origin: I64Vec3,
+    /// Chunks to mesh again
+    dirty: HashSet<I64Vec3>,
+    /// Child entities drawing each chunk, updated in place like the world's chunks
+    rendered: HashMap<I64Vec3, RenderedChunk>,
+}
+
+impl VoxelBody {
+    pub fn new(source_origin: I64Vec3) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            source_origin,
+            dirty: HashSet::new(),
+            rendered: HashMap::new(),
+        }
+    }
+
+    /// Set the voxel at a position in the body's own grid, growing the grid as needed
+    pub fn set_voxel(&mut self, local_pos: IVec3, voxel: Voxel) {
+        let (chunk_pos, voxel_pos) = ChunkManager::world_to_chunk_coords(&local_pos.into());
+        let chunk = self.chunks.entry(chunk_pos).or_default();
+        if let Some(chunk_voxel) = chunk.get_mut_voxel(Chunk::get_index(&voxel_pos)) {
+            *chunk_voxel = voxel;
+        }
+        self.dirty.insert(chunk_pos);
+
+        // The neighbours' border faces depend on the voxel too
+        for offset in NEIGHBOUR_OFFSETS {
+            let (neighbour_pos, _) =
+                ChunkManager::world_to_chunk_coords(&(local_pos + offset).into());
+            if self.chunks.contains_key(&neighbour_pos) {
+                self.dirty.insert(neighbour_pos);
+            }
+        }
+    }
+
+    /// Position in the body's own grid of the voxel a point in the render space lies in,
+    /// like where a ray hit it
+    pub fn local_voxel_pos(transform: &GlobalTransform, point: Vec3) -> IVec3 {
+        transform
+            .affine()
+            .inverse()
+            .transform_point3(point)
+            .round()
+            .as_ivec3()
+    }
+
+    /// Compound of boxes over the solid voxels, merged along rows so there are few of them.
+    /// Unlike a trimesh it has a volume, so Rapier can give the body its mass
+    fn collider(&self) -> Option<Collider> {
+        let mut shapes = Vec::new();
+        for (chunk_pos, chunk) in self.chunks.iter() {
+            let chunk_offset = chunk_pos.as_ivec3() * CHUNK_SIZE as i32;
+            for x in 0..CHUNK_SIZE as i32 {
+                for y in 0..CHUNK_SIZE as i32 {
+                    let mut run_start = None;
+                    for z in 0..=CHUNK_SIZE as i32 {
+                        let solid = z < CHUNK_SIZE as i32
+                            && is_solid(&chunk.voxels[Chunk::get_index(&IVec3::new(x, y, z))]);
+                        match (solid, run_start) {
+                            (true, None) => run_start = Some(z),
+                            (false, Some(start)) => {
+                                let length = (z - start) as f32;
+                                let center = (chunk_offset + IVec3::new(x, y, start)).as_vec3()
+                                    + Vec3::Z * (length - 1.0) / 2.0;
+                                let shape = Collider::cuboid(0.5, 0.5, length / 2.0);
+                                shapes.push((center, Quat::IDENTITY, shape));
+                                run_start = None;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        (!shapes.is_empty()).then(|| Collider::compound(shapes))
+    }
+}
+
+/// Fluids are left behind, they'd only flow out of the body
+fn is_solid(voxel: &Voxel) -> bool {
+    voxel.active && !voxel.voxel_type.is_fluid()
+}
+
+/// Move the solid voxels from `min` to `max`, world voxel positions, out of the world and
+/// into a new voxel body in their place. None if there were none
+pub fn spawn_voxel_body(
+    commands: &mut Commands,
+    chunk_manager: &mut ChunkManager,
+    min: I64Vec3,
+    max: I64Vec3,
+) -> Option<Entity> {
+    let mut body = VoxelBody::new(min);
+    let mut edits = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let world_pos = I64Vec3::new(x, y, z);
+                let Ok(voxel) = chunk_manager.get_world_voxel(&world_pos) else { continue; };
+                if !is_solid(voxel) {
+                    continue;
+                }
+                body.set_voxel((world_pos - min).as_ivec3(), *voxel);
+                edits.push((world_pos, Voxel::new_empty()));
+            }
+        }
+    }
+    let collider = body.collider()?;
+    chunk_manager.update_voxels(&edits);
+
+    let transform = Transform::from_translation(chunk_manager.world_to_render(min));
+    let entity = commands
+        .spawn((
+            SpatialBundle::from_transform(transform),
+            RigidBody::Dynamic,
+            collider,
+            body,
+            Name::new("Voxel Body"),
+        ))
+        .id();
+    Some(entity)
+}
+
+fn mesh_voxel_bodies(
+    mut commands: Commands,
+    world_gen_settings: Res<WorldGenSettings>,
+    render_settings: Res<RenderSettings>,
+    block_materials: Res<BlockMaterials>,
+    chunk_manager: Res<ChunkManager>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut body_query: Query<(Entity, &mut VoxelBody)>,
+) {
+    // Another meshing mode, vertex format or material may be drawn by another kind of
+    // material, so the submeshes are spawned again. Other world gen settings tint the
+    // blocks differently
+    let remesh_all = world_gen_settings.is_changed()
+        || render_settings.is_changed()
+        || block_materials.is_changed();
+    for (entity, mut body) in body_query.iter_mut() {
+        let body = &mut *body;
+        if remesh_all {
+            for rendered in body.rendered.values_mut() {
+                rendered.clear_submeshes(&mut commands);
+            }
+            body.dirty.extend(body.chunks.keys().copied());
+        }
+        if body.dirty.is_empty() {
+            continue;
+        }
+        for chunk_pos in std::mem::take(&mut body.dirty) {
+            let empty = body
+                .chunks
+                .get_mut(&chunk_pos)
+                .is_none_or(|chunk| chunk.check_empty());
+            if empty {
+                body.chunks.remove(&chunk_pos);
+                if let Some(rendered) = body.rendered.remove(&chunk_pos) {
+                    commands.entity(rendered.entity).despawn_recursive();
+                }
+                continue;
+            }
+
+            // There's nothing around the body, so its outside is meshed as air
+            let chunk = &body.chunks[&chunk_pos];
+            let padded_chunk = PaddedChunk::with_neighbours(
+                chunk,
+                &chunk_pos,
+                body.source_origin + chunk_pos * CHUNK_SIZE as i64,
+                MissingNeighbours::Air,
+                chunk_manager.generator(),
+                chunk_manager.tint_overrides(),
+                |neighbour_pos| body.chunks.get(neighbour_pos),
+            );
+            let Some(padded_chunk) = padded_chunk else { continue; };
+            let submeshes = chunk_manager.mesh_padded_chunk(&padded_chunk, &MeshLod::default());
+            let rendered = body.rendered.entry(chunk_pos).or_insert_with(|| {
+                let translation = (chunk_pos * CHUNK_SIZE as i64).as_vec3();
+                let rendered =
+                    RenderedChunk::spawn(&mut commands, Transform::from_translation(translation));
+                commands.entity(entity).add_child(rendered.entity);
+                rendered
+            });
+            rendered.update_submeshes(
+                &mut commands,
+                &mut meshes,
+                chunk_manager.block_materials(),
+                &submeshes,
+            );
+        }
+
+        // A body with all of its voxels removed is gone
+        match body.collider() {
+            Some(collider) => {
+                commands.entity(entity).insert(collider);
+            }
+            None => {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
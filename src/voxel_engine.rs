@@ -1,7 +1,26 @@
-use bevy::{prelude::*, render::primitives::Frustum};
+use bevy::{
+    prelude::*,
+    render::{primitives::Frustum, render_resource::TextureFormat},
+};
 use bevy_rapier3d::prelude::*;
 
+use crate::block_materials::{BlockMaterials, BlockMaterialsPlugin};
 use crate::chunk_manager::ChunkManager;
+use crate::chunk_transition::ChunkTransitionPlugin;
+use crate::emissive_map::{empty_emissive_map, no_emission_map, EmissiveMapPlugin};
+use crate::far_terrain::{FarTerrainMaterial, FarTerrainPlugin};
+use crate::floating_origin::FloatingOriginPlugin;
+use crate::resource_packs::{
+    ResourcePacksPlugin, NORMAL_MAP_FILE, SPRITESHEET_FILE, SURFACE_MAP_FILE,
+};
+use crate::structures::{StructureTemplate, STRUCTURES_PATH};
+use crate::texture_animation::TextureAnimationPlugin;
+use crate::tint_overrides::TintOverridesPlugin;
+use crate::voxel::BlockMaterial;
+use crate::voxel_body::VoxelBodyPlugin;
+use crate::voxel_material::VoxelMaterial;
+use crate::render_settings::{RenderSettings, RenderSettingsPlugin};
+use crate::world_gen_settings::{WorldGenSettings, WorldGenSettingsPlugin};
 
 pub struct VoxelEnginePlugin;
 
@@ -9,14 +28,31 @@ impl Plugin for VoxelEnginePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
             // .add_plugin(RapierDebugRenderPlugin::default())
+            .add_plugin(WorldGenSettingsPlugin)
+            .add_plugin(RenderSettingsPlugin)
+            .add_plugin(FarTerrainPlugin)
+            .add_plugin(MaterialPlugin::<VoxelMaterial>::default())
+            .add_plugin(MaterialPlugin::<FarTerrainMaterial>::default())
+            .add_plugin(TextureAnimationPlugin)
+            .add_plugin(EmissiveMapPlugin)
+            .add_plugin(BlockMaterialsPlugin)
+            .add_plugin(ResourcePacksPlugin)
+            .add_plugin(TintOverridesPlugin)
+            .add_plugin(ChunkTransitionPlugin)
+            .add_plugin(FloatingOriginPlugin)
+            .add_plugin(VoxelBodyPlugin)
             .add_startup_system(load_resources)
             .add_systems((
+                apply_world_gen_settings,
+                apply_render_settings.after(apply_world_gen_settings),
+                load_maps_as_linear,
                 load_chunks,
                 load_meshes,
                 rebuild_data,
                 unload_chunks,
                 unload_meshes,
                 check_visibility,
+                cull_occluded_chunks,
                 render,
             ))
             .init_resource::<ChunkManager>();
@@ -25,24 +61,102 @@ impl Plugin for VoxelEnginePlugin {
 
 fn load_resources(
     mut chunk_manager: ResMut<ChunkManager>,
+    mut block_materials: ResMut<BlockMaterials>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut voxel_materials: ResMut<Assets<VoxelMaterial>>,
+    mut images: ResMut<Assets<Image>>,
     asset_server: Res<AssetServer>,
 ) {
-    let spritesheet_handle = asset_server.load("spritesheet.png");
-    let material_handle = materials.add(StandardMaterial {
-        base_color: Color::rgba(1.0, 1.0, 1.0, 1.0),
-        base_color_texture: Some(spritesheet_handle.clone()),
-        alpha_mode: AlphaMode::Opaque,
-        unlit: false,
-        fog_enabled: true,
-        metallic: 0.0,
-        perceptual_roughness: 1.0,
-        reflectance: 0.125,
-        ..default()
-    });
+    // The resource packs switch to their own spritesheet and material parameters
+    let spritesheet_handle = asset_server.load(SPRITESHEET_FILE);
+    let normal_map_handle = asset_server.load(NORMAL_MAP_FILE);
+    let surface_map_handle: Handle<Image> = asset_server.load(SURFACE_MAP_FILE);
+    let emissive_map_handle = images.add(empty_emissive_map());
+    let no_emission_handle = images.add(no_emission_map());
+    for material in BlockMaterial::BUILT_IN {
+        let alpha_mode = match material {
+            BlockMaterial::CUTOUT => AlphaMode::Mask(0.5),
+            BlockMaterial::TRANSLUCENT => AlphaMode::Blend,
+            _ => AlphaMode::Opaque,
+        };
+        // Only emissive blocks glow, the rest of the emissive map is black anyway
+        let emissive_map = if material == BlockMaterial::EMISSIVE {
+            emissive_map_handle.clone()
+        } else {
+            no_emission_handle.clone()
+        };
+        let standard = materials.add(StandardMaterial {
+            base_color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: Some(spritesheet_handle.clone()),
+            emissive: Color::WHITE,
+            emissive_texture: Some(emissive_map.clone()),
+            normal_map_texture: Some(normal_map_handle.clone()),
+            metallic_roughness_texture: Some(surface_map_handle.clone()),
+            occlusion_texture: Some(surface_map_handle.clone()),
+            alpha_mode,
+            unlit: false,
+            fog_enabled: true,
+            // Scaling the surface map
+            metallic: 1.0,
+            perceptual_roughness: 1.0,
+            reflectance: 0.125,
+            ..default()
+        });
+        let packed = voxel_materials.add(
+            VoxelMaterial::new(
+                spritesheet_handle.clone(),
+                emissive_map,
+                normal_map_handle.clone(),
+                surface_map_handle.clone(),
+            )
+            .with_alpha_mode(alpha_mode),
+        );
+        block_materials.set_spritesheet_materials(material, standard, packed);
+    }
 
     chunk_manager.spritesheet_handle = spritesheet_handle;
-    chunk_manager.material_handle = material_handle;
+    chunk_manager.emissive_map_handle = emissive_map_handle;
+    chunk_manager.normal_map_handle = normal_map_handle;
+    chunk_manager.surface_map_handle = surface_map_handle;
+    chunk_manager.set_structures(StructureTemplate::load_dir(STRUCTURES_PATH));
+}
+
+pub fn apply_world_gen_settings(
+    commands: Commands,
+    settings: Res<WorldGenSettings>,
+    mut chunk_manager: ResMut<ChunkManager>,
+) {
+    if settings.is_changed() {
+        chunk_manager.set_world_gen_settings(&settings, commands);
+    }
+}
+
+pub fn apply_render_settings(
+    commands: Commands,
+    settings: Res<RenderSettings>,
+    mut chunk_manager: ResMut<ChunkManager>,
+) {
+    if settings.is_changed() {
+        chunk_manager.set_render_settings(&settings, commands);
+    }
+}
+
+/// Images load as sRGB colour, but the normal and surface maps hold plain numbers
+fn load_maps_as_linear(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    chunk_manager: Res<ChunkManager>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for event in image_events.iter() {
+        let AssetEvent::Created { handle } = event else { continue; };
+        if *handle != chunk_manager.normal_map_handle && *handle != chunk_manager.surface_map_handle
+        {
+            continue;
+        }
+        if let Some(map) = images.get_mut(handle) {
+            map.texture_descriptor.format = TextureFormat::Rgba8Unorm;
+        }
+    }
 }
 
 fn load_chunks(mut chunk_manager: ResMut<ChunkManager>) {
@@ -73,6 +187,35 @@ fn check_visibility(
     chunk_manager.update_visible(transform, frustrum);
 }
 
+/// Hide the chunks the camera can't see into, see `ChunkManager::visible_chunks`
+fn cull_occluded_chunks(
+    camera_query: Query<&Transform, With<Camera3d>>,
+    chunk_manager: Res<ChunkManager>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    let Ok(transform) = camera_query.get_single() else { return; };
+    let camera_chunk_pos = chunk_manager.chunk_pos_at(transform.translation);
+    let visible = chunk_manager
+        .occlusion_culling
+        .then(|| chunk_manager.visible_chunks(camera_chunk_pos));
+    for (chunk_pos, entity) in chunk_manager.rendered_chunks() {
+        // Chunks spawned this frame are culled on the next
+        let Ok(mut visibility) = visibilities.get_mut(entity) else { continue; };
+        let hidden = visible
+            .as_ref()
+            .is_some_and(|visible| !visible.contains(&chunk_pos));
+        let new_visibility = if hidden {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        // Only written when it changes, so change detection stays quiet
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+}
+
 fn render(
     commands: Commands,
     meshes: ResMut<Assets<Mesh>>,
@@ -1,7 +1,13 @@
 use bevy::{pbr::NotShadowCaster, prelude::*, window::PrimaryWindow};
 use bevy_rapier3d::prelude::*;
 
-use crate::{chunk_manager::ChunkManager, voxel::VoxelType, MyCamera};
+use crate::{
+    chunk_manager::ChunkManager,
+    voxel::{Voxel, VoxelType},
+    voxel_body::{spawn_voxel_body, VoxelBody, MAX_VOXEL_BODY_SIZE},
+    world_coords::I64Vec3,
+    MyCamera,
+};
 
 pub struct VoxelInteractionPlugin;
 
@@ -51,9 +57,11 @@ fn setup_voxel_indicator(
     ));
 }
 
+#[allow(clippy::too_many_arguments)]
 fn mouse_interaction(
-    _commands: Commands,
+    mut commands: Commands,
     mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
     camera_query: Query<(&Camera, &GlobalTransform), With<MyCamera>>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     rapier_context: Res<RapierContext>,
@@ -63,6 +71,8 @@ fn mouse_interaction(
         With<VoxelIndicator>,
     >,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut body_query: Query<(&GlobalTransform, &mut VoxelBody)>,
+    mut region_corner: Local<Option<I64Vec3>>,
 ) {
     let Ok(window) = window_query.get_single() else { return; };
     let Some(cursor_position) = window.cursor_position() else { return; };
@@ -83,6 +93,29 @@ fn mouse_interaction(
         if let Some(chunk_pos) = chunk_manager.get_chunk_pos_by_entity(entity) {
             *selector_visibility = Visibility::Inherited;
 
+            // R marks a corner of a region, and again at the opposite corner cuts the region
+            // out into a voxel body
+            if keys.just_pressed(KeyCode::R) {
+                let corner = chunk_manager.render_to_world(ray.get_point(toi + 0.01));
+                match region_corner.take() {
+                    None => {
+                        println!("Voxel body region from {}", corner);
+                        *region_corner = Some(corner);
+                    }
+                    Some(first_corner) => {
+                        let (min, max) = (first_corner.min(corner), first_corner.max(corner));
+                        if (max - min)
+                            .cmpge(I64Vec3::splat(MAX_VOXEL_BODY_SIZE as i64))
+                            .any()
+                        {
+                            println!("Voxel body region from {} to {} is too large", min, max);
+                        } else {
+                            spawn_voxel_body(&mut commands, &mut chunk_manager, min, max);
+                        }
+                    }
+                }
+            }
+
             // Left mouse click - Create voxels
             if mouse.pressed(MouseButton::Left) {
                 let hit_point = ray.get_point(toi - 0.01);
@@ -113,6 +146,18 @@ fn mouse_interaction(
                     material.base_color = selector_color(SelectorColor::Default);
                 }
             }
+        } else if let Ok((body_transform, mut body)) = body_query.get_mut(entity) {
+            // Edited in the body's own grid, the indicator doesn't turn along with it
+            *selector_visibility = Visibility::Hidden;
+            if mouse.just_released(MouseButton::Left) {
+                let hit_point = ray.get_point(toi - 0.01);
+                let local_pos = VoxelBody::local_voxel_pos(body_transform, hit_point);
+                body.set_voxel(local_pos, Voxel::from_type(VoxelType::Grass));
+            } else if mouse.just_released(MouseButton::Right) {
+                let hit_point = ray.get_point(toi + 0.01);
+                let local_pos = VoxelBody::local_voxel_pos(body_transform, hit_point);
+                body.set_voxel(local_pos, Voxel::new_empty());
+            }
         } else {
             *selector_visibility = Visibility::Hidden;
             material.base_color = selector_color(SelectorColor::Default);
@@ -0,0 +1,222 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey, MeshPipelineKey},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+            VertexFormat,
+        },
+    },
+};
+
+use crate::face::{Face, Side, HALF_SIZE};
+use crate::voxel_textures::{
+    in_spritesheet, SPRITE_COLUMNS, SPRITE_OFFSET, SPRITE_ROWS, SPRITE_SIZE, TEXTURE_HEIGHT,
+    TEXTURE_WIDTH,
+};
+
+pub const VOXEL_SHADER_PATH: &str = "shaders/voxel.wgsl";
+
+/// Vertex of a blocky chunk mesh packed into two u32s, see `pack_vertex`
+pub const ATTRIBUTE_PACKED_VOXEL: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_PackedVoxel", 988_540_917, VertexFormat::Uint32x2);
+
+/// Sprites of the block at a vertex of a smooth chunk mesh, see `pack_smooth_sprites`
+pub const ATTRIBUTE_SMOOTH_SPRITES: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_SmoothSprites", 988_540_918, VertexFormat::Uint32x2);
+
+/// Ambient occlusion of a vertex nothing is in front of
+pub const NO_OCCLUSION: u32 = 3;
+/// Brightness of a fully occluded vertex, the rest going up evenly to 1
+const OCCLUDED_BRIGHTNESS: f32 = 0.4;
+
+const POSITION_BITS: u32 = 5;
+const SIDE_SHIFT: u32 = POSITION_BITS * 3;
+const CORNER_SHIFT: u32 = SIDE_SHIFT + 3;
+const OCCLUSION_SHIFT: u32 = CORNER_SHIFT + 2;
+const ROTATION_SHIFT: u32 = OCCLUSION_SHIFT + 2;
+const POSITION_MASK: u32 = (1 << POSITION_BITS) - 1;
+const TINT_SHIFT: u32 = 16;
+// Sprites are packed as their column and row, 8 bits each, which holds every sprite in the
+// spritesheet. Resource packs can't give blocks sprites outside of it
+const _: () = assert!(SPRITE_COLUMNS <= 1 << 8 && SPRITE_ROWS <= 1 << 8);
+
+/// Material for chunk meshes with packed vertices, unpacked and lit like a `StandardMaterial`
+/// by `VOXEL_SHADER_PATH`, and for smooth chunk meshes, textured there by triplanar projection
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "5b0f3c8e-9d2a-4e71-a6c4-3f8e1d27b9a0"]
+pub struct VoxelMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub spritesheet: Handle<Image>,
+    /// Width and height of the spritesheet, and size and padding of its sprites, in pixels
+    #[uniform(2)]
+    pub sprite_layout: Vec4,
+    #[texture(3)]
+    #[sampler(4)]
+    pub emissive_map: Handle<Image>,
+    /// Perceptual roughness, metallic and reflectance, like the fields of `StandardMaterial`,
+    /// and the alpha cutoff of `AlphaMode::Mask`. The roughness and metallic scale those of
+    /// the `surface_map`
+    #[uniform(5)]
+    pub surface: Vec4,
+    /// Tangent space normals of the sprites, in the layout of the spritesheet, with green
+    /// pointing down the sprite
+    #[texture(6)]
+    #[sampler(7)]
+    pub normal_map: Handle<Image>,
+    /// Occlusion, roughness and metallic of the sprites in the red, green and blue channels
+    #[texture(8)]
+    #[sampler(9)]
+    pub surface_map: Handle<Image>,
+    pub alpha_mode: AlphaMode,
+}
+
+impl VoxelMaterial {
+    pub fn new(
+        spritesheet: Handle<Image>,
+        emissive_map: Handle<Image>,
+        normal_map: Handle<Image>,
+        surface_map: Handle<Image>,
+    ) -> Self {
+        Self {
+            spritesheet,
+            sprite_layout: Vec4::new(TEXTURE_WIDTH, TEXTURE_HEIGHT, SPRITE_SIZE, SPRITE_OFFSET),
+            emissive_map,
+            surface: Vec4::new(1.0, 1.0, 0.125, 0.0),
+            normal_map,
+            surface_map,
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self.surface.w = match alpha_mode {
+            AlphaMode::Mask(cutoff) => cutoff,
+            _ => 0.0,
+        };
+        self
+    }
+}
+
+impl Material for VoxelMaterial {
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn vertex_shader() -> ShaderRef {
+        VOXEL_SHADER_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        VOXEL_SHADER_PATH.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let smooth = !layout.contains(ATTRIBUTE_PACKED_VOXEL);
+        let vertex_layout = if smooth {
+            descriptor.vertex.shader_defs.push("VOXEL_SMOOTH".into());
+            layout.get_layout(&[
+                Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+                Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+                Mesh::ATTRIBUTE_COLOR.at_shader_location(2),
+                ATTRIBUTE_SMOOTH_SPRITES.at_shader_location(3),
+            ])?
+        } else {
+            layout.get_layout(&[ATTRIBUTE_PACKED_VOXEL.at_shader_location(0)])?
+        };
+        descriptor.vertex.buffers = vec![vertex_layout];
+        // Otherwise the lighting drops the alpha, as for opaque materials
+        let blend = key.mesh_key & MeshPipelineKey::BLEND_RESERVED_BITS;
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            if smooth {
+                fragment.shader_defs.push("VOXEL_SMOOTH".into());
+            }
+            if blend == MeshPipelineKey::BLEND_ALPHA {
+                fragment.shader_defs.push("VOXEL_ALPHA_BLEND".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pack a vertex of a face into the first u32 as the x, y and z of the voxel corner it's on,
+/// 5 bits each, then the side, 3 bits, the corner of the sprite, 2 bits, the ambient occlusion,
+/// 2 bits, and the quarter turns of the sprite, which give the tangent, 2 bits, and into the
+/// second u32 as the column and row of the sprite, 8 bits each, then the tint as 5 bits of red,
+/// 6 of green and 5 of blue. 8 bytes instead of the 64 of position, normal, tangent, UV and
+/// colour. Bits 24 to 31 of the first u32 are free for light once there is some
+pub fn pack_vertex(face: &Face, corner: usize) -> [u32; 2] {
+    debug_assert!(
+        in_spritesheet(face.sprite),
+        "sprite {} outside of the spritesheet",
+        face.sprite
+    );
+    let corner_pos = (face.vertices[corner] + Vec3::splat(HALF_SIZE))
+        .round()
+        .as_uvec3();
+    let sprite_corner = (corner + face.uv_rotation) % 4;
+    [
+        (corner_pos.x & POSITION_MASK)
+            | (corner_pos.y & POSITION_MASK) << POSITION_BITS
+            | (corner_pos.z & POSITION_MASK) << (POSITION_BITS * 2)
+            | side_index(face.side) << SIDE_SHIFT
+            | (sprite_corner as u32) << CORNER_SHIFT
+            | face.occlusion[corner] << OCCLUSION_SHIFT
+            | (face.uv_rotation as u32) << ROTATION_SHIFT,
+        (face.sprite.x & 0xff) | (face.sprite.y & 0xff) << 8 | pack_tint(face.tint) << TINT_SHIFT,
+    ]
+}
+
+/// How much the colour of a vertex is darkened by its ambient occlusion, kept in step with
+/// the packed vertex shader
+pub fn occlusion_brightness(occlusion: u32) -> f32 {
+    OCCLUDED_BRIGHTNESS + (1.0 - OCCLUDED_BRIGHTNESS) * occlusion as f32 / NO_OCCLUSION as f32
+}
+
+/// Pack the sprites of the top and bottom of a block into the first u32, 16 bits each, and
+/// that of its sides into the second, each as its column and row, 8 bits each
+pub fn pack_smooth_sprites(top: UVec2, bottom: UVec2, side: UVec2) -> [u32; 2] {
+    debug_assert!([top, bottom, side].into_iter().all(in_spritesheet));
+    let pack = |sprite: UVec2| (sprite.x & 0xff) | (sprite.y & 0xff) << 8;
+    [pack(top) | pack(bottom) << 16, pack(side)]
+}
+
+/// The tint as RGB565
+fn pack_tint(tint: Vec3) -> u32 {
+    let [r, g, b] = (tint.clamp(Vec3::ZERO, Vec3::ONE) * Vec3::new(31.0, 63.0, 31.0))
+        .round()
+        .as_uvec3()
+        .to_array();
+    r << 11 | g << 5 | b
+}
+
+/// Position of a packed vertex within its chunk, as `Face` would have put it
+pub fn unpack_position(packed: [u32; 2]) -> Vec3 {
+    let corner_pos = UVec3::new(
+        packed[0] & POSITION_MASK,
+        packed[0] >> POSITION_BITS & POSITION_MASK,
+        packed[0] >> (POSITION_BITS * 2) & POSITION_MASK,
+    );
+    corner_pos.as_vec3() - Vec3::splat(HALF_SIZE)
+}
+
+/// Index of the side in the shader's table of normals
+fn side_index(side: Side) -> u32 {
+    match side {
+        Side::Right => 0,
+        Side::Left => 1,
+        Side::Top => 2,
+        Side::Bottom => 3,
+        Side::Front => 4,
+        Side::Back => 5,
+    }
+}
@@ -1,25 +1,225 @@
-use bevy::prelude::Vec2;
+use std::ops::Range;
+
+use bevy::{
+    prelude::{UVec2, Vec2, Vec3},
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{face::Side, face::Side::*, voxel::VoxelType, voxel::VoxelType::*};
 
-const TEXTURE_WIDTH: f32 = 512.0;
-const TEXTURE_HEIGHT: f32 = 512.0;
-const SPRITE_SIZE: f32 = 4.0;
-const SPRITE_OFFSET: f32 = 1.0;
+pub const TEXTURE_WIDTH: f32 = 512.0;
+pub const TEXTURE_HEIGHT: f32 = 512.0;
+pub const SPRITE_SIZE: f32 = 4.0;
+pub const SPRITE_OFFSET: f32 = 1.0;
+/// Bytes per pixel of the spritesheet, which is RGBA8
+pub const PIXEL_SIZE: usize = 4;
+/// Pixels along each side of a sprite, padding included
+pub const SPRITE_CELL: usize = (SPRITE_SIZE + SPRITE_OFFSET * 2.0) as usize;
+/// Columns and rows of sprites the spritesheet holds
+pub const SPRITE_COLUMNS: u32 = TEXTURE_WIDTH as u32 / SPRITE_CELL as u32;
+pub const SPRITE_ROWS: u32 = TEXTURE_HEIGHT as u32 / SPRITE_CELL as u32;
+
+/// Block texture cycling through a strip of frames. Each frame is copied over the sprite
+/// the block is meshed with, so the meshes don't change
+pub struct AnimatedTexture {
+    pub voxel_type: VoxelType,
+    /// Sprite of the first frame, the others follow to the right of it
+    pub strip: UVec2,
+    /// How long each frame is shown, in seconds
+    pub frame_durations: &'static [f32],
+}
+
+pub const ANIMATED_TEXTURES: [AnimatedTexture; 2] = [
+    AnimatedTexture {
+        voxel_type: Water,
+        strip: UVec2::new(0, 4),
+        frame_durations: &[0.3, 0.3, 0.3, 0.3],
+    },
+    AnimatedTexture {
+        voxel_type: Lava,
+        strip: UVec2::new(0, 5),
+        frame_durations: &[0.8, 0.4, 0.4, 0.4],
+    },
+];
+
+/// How the sprite of a block side is picked when it's meshed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureRule {
+    /// Always the sprite from `BlockTextures::sprite`
+    Single,
+    /// That sprite or one of the `count` variants in the strip starting at `strip`, picked,
+    /// and turned if `rotate`, by the position of the block
+    Random {
+        strip: UVec2,
+        count: u32,
+        rotate: bool,
+    },
+    /// One of the 16 sprites in the strip starting at `strip`, picked by which of the four
+    /// neighbours along the face it joins, with a bit each for up, right, down and left
+    Connected { strip: UVec2 },
+}
+
+/// Sprites of a block's sides, given as column and row in the spritesheet. Sides left out
+/// fall back on `all`, and then on the built-in sprite
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockModel {
+    pub all: Option<(u32, u32)>,
+    pub top: Option<(u32, u32)>,
+    pub bottom: Option<(u32, u32)>,
+    /// The four sides around the block
+    pub side: Option<(u32, u32)>,
+}
+
+impl BlockModel {
+    pub fn sprite(&self, side: Side) -> Option<UVec2> {
+        let sprite = match side {
+            Top => self.top,
+            Bottom => self.bottom,
+            _ => self.side,
+        };
+        sprite.or(self.all).map(|(x, y)| UVec2::new(x, y))
+    }
+
+    /// The first of its sprites lying outside of the spritesheet, if any
+    pub fn sprite_outside_spritesheet(&self) -> Option<UVec2> {
+        [self.all, self.top, self.bottom, self.side]
+            .into_iter()
+            .flatten()
+            .map(|(x, y)| UVec2::new(x, y))
+            .find(|sprite| !in_spritesheet(*sprite))
+    }
+
+    /// This model with the sides given by `other` replaced
+    pub fn merged(&self, other: &BlockModel) -> BlockModel {
+        BlockModel {
+            all: other.all.or(self.all),
+            top: other.top.or(self.top),
+            bottom: other.bottom.or(self.bottom),
+            side: other.side.or(self.side),
+        }
+    }
+}
+
+/// The sprites of the blocks, the built-in ones with the models of the resource packs on top
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockTextures {
+    models: HashMap<VoxelType, BlockModel>,
+}
+
+impl BlockTextures {
+    /// Lay the model over the one the block has so far. Models with a sprite outside of
+    /// the spritesheet are left out, failing with that sprite
+    pub fn add_model(&mut self, voxel_type: VoxelType, model: &BlockModel) -> Result<(), UVec2> {
+        if let Some(sprite) = model.sprite_outside_spritesheet() {
+            return Err(sprite);
+        }
+        let merged = self.models.entry(voxel_type).or_default().merged(model);
+        self.models.insert(voxel_type, merged);
+        Ok(())
+    }
+
+    pub fn sprite(&self, voxel_type: VoxelType, side: Side) -> UVec2 {
+        self.model_sprite(voxel_type, side)
+            .unwrap_or_else(|| get_voxel_type_sprite(voxel_type, side))
+    }
 
-pub fn get_voxel_type_uv(voxel_type: VoxelType, side: Side) -> [Vec2; 4] {
+    /// The built-in rule of the block side, unless a model replaced its sprite, as the
+    /// variants of the built-in sprite won't go with it
+    pub fn rule(&self, voxel_type: VoxelType, side: Side) -> TextureRule {
+        if self.is_built_in(voxel_type, side) {
+            get_voxel_type_rule(voxel_type, side)
+        } else {
+            TextureRule::Single
+        }
+    }
+
+    /// Whether the block side has its built-in sprite
+    pub fn is_built_in(&self, voxel_type: VoxelType, side: Side) -> bool {
+        self.model_sprite(voxel_type, side).is_none()
+    }
+
+    fn model_sprite(&self, voxel_type: VoxelType, side: Side) -> Option<UVec2> {
+        self.models.get(&voxel_type)?.sprite(side)
+    }
+}
+
+/// Whether the column and row are those of a sprite in the spritesheet
+pub fn in_spritesheet(sprite: UVec2) -> bool {
+    sprite.x < SPRITE_COLUMNS && sprite.y < SPRITE_ROWS
+}
+
+pub fn get_sprite_uv(sprite: UVec2) -> [Vec2; 4] {
+    get_uv_for_index(sprite.x as usize, sprite.y as usize)
+}
+
+pub fn get_voxel_type_rule(voxel_type: VoxelType, side: Side) -> TextureRule {
+    let random = |x: u32| TextureRule::Random {
+        strip: UVec2::new(x, 7),
+        count: 3,
+        rotate: true,
+    };
+    match (voxel_type, side) {
+        (Grass, Top) => random(0),
+        (Dirt, _) | (Grass, Bottom) => random(3),
+        (Stone, _) => random(6),
+        (Sand, _) => random(9),
+        (Bedrock, _) => TextureRule::Connected {
+            strip: UVec2::new(0, 6),
+        },
+        _ => TextureRule::Single,
+    }
+}
+
+/// Column and row of the voxel type's sprite in the spritesheet
+pub fn get_voxel_type_sprite(voxel_type: VoxelType, side: Side) -> UVec2 {
     match voxel_type {
-        Default => get_uv_for_index(0, 0),
-        None => get_uv_for_index(0, 0),
-        Dirt => get_uv_for_index(1, 0),
+        Default => UVec2::new(0, 0),
+        None => UVec2::new(0, 0),
+        Dirt => UVec2::new(1, 0),
         Grass => match side {
-            Top => get_uv_for_index(2, 0),
-            Bottom => get_uv_for_index(1, 0),
-            _ => get_uv_for_index(3, 0),
+            Top => UVec2::new(2, 0),
+            Bottom => UVec2::new(1, 0),
+            _ => UVec2::new(3, 0),
         },
+        Sand => UVec2::new(0, 1),
+        Water => UVec2::new(1, 1),
+        Stone => UVec2::new(2, 1),
+        Lava => UVec2::new(3, 1),
+        Bedrock => UVec2::new(4, 1),
     }
 }
 
+/// Sprites of the emissive blocks, their variants included, with the colour they glow in
+pub fn emissive_sprites(block_textures: &BlockTextures) -> Vec<(UVec2, Vec3)> {
+    let mut sprites = Vec::new();
+    for voxel_type in VoxelType::ALL {
+        let Some(color) = voxel_type.emissive_color() else { continue; };
+        for side in [Right, Left, Top, Bottom, Front, Back] {
+            sprites.push((block_textures.sprite(voxel_type, side), color));
+            let (strip, count) = match block_textures.rule(voxel_type, side) {
+                TextureRule::Single => continue,
+                TextureRule::Random { strip, count, .. } => (strip, count),
+                TextureRule::Connected { strip } => (strip, 16),
+            };
+            sprites.extend((0..count).map(|index| (strip + UVec2::X * index, color)));
+        }
+    }
+    sprites
+}
+
+/// Byte ranges of the rows of the sprite's pixels in the spritesheet, padding included
+pub fn sprite_cell_rows(sprite: UVec2) -> impl Iterator<Item = Range<usize>> {
+    let row_size = TEXTURE_WIDTH as usize * PIXEL_SIZE;
+    let cell_size = SPRITE_CELL * PIXEL_SIZE;
+    let start = sprite.y as usize * SPRITE_CELL * row_size + sprite.x as usize * cell_size;
+    (0..SPRITE_CELL).map(move |row| {
+        let row_start = start + row * row_size;
+        row_start..row_start + cell_size
+    })
+}
+
 fn get_uv_for_index(x: usize, y: usize) -> [Vec2; 4] {
     let x0: f32 = x as f32;
     let y0: f32 = y as f32;
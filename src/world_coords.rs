@@ -0,0 +1,203 @@
+use std::{
+    fmt,
+    ops::{Add, Mul, Sub},
+};
+
+use bevy::prelude::{BVec3, IVec2, IVec3, Vec3};
+
+/// Position in the world, in chunks or in voxels. i64, so the world runs on for longer than
+/// anyone can travel. Offsets between nearby positions, like those to neighbours or to the
+/// render origin, stay `IVec3`, and only they are turned into f32 for the render space
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct I64Vec3 {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl I64Vec3 {
+    pub const ZERO: I64Vec3 = I64Vec3::splat(0);
+    pub const Y: I64Vec3 = I64Vec3::new(0, 1, 0);
+
+    pub const fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub const fn splat(value: i64) -> Self {
+        Self::new(value, value, value)
+    }
+
+    pub fn to_array(&self) -> [i64; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        Self::new(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.z.min(other.z),
+        )
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        Self::new(
+            self.x.max(other.x),
+            self.y.max(other.y),
+            self.z.max(other.z),
+        )
+    }
+
+    pub fn cmpge(self, other: Self) -> BVec3 {
+        BVec3::new(self.x >= other.x, self.y >= other.y, self.z >= other.z)
+    }
+
+    pub fn cmple(self, other: Self) -> BVec3 {
+        BVec3::new(self.x <= other.x, self.y <= other.y, self.z <= other.z)
+    }
+
+    /// Per axis, the chunk a world voxel position lies in for a `size` of `CHUNK_SIZE`
+    pub fn div_euclid(self, size: i64) -> Self {
+        Self::new(
+            self.x.div_euclid(size),
+            self.y.div_euclid(size),
+            self.z.div_euclid(size),
+        )
+    }
+
+    /// Per axis, the position within the chunk for a `size` of `CHUNK_SIZE`, which always fits
+    /// an `IVec3`
+    pub fn rem_euclid(self, size: i64) -> IVec3 {
+        IVec3::new(
+            self.x.rem_euclid(size) as i32,
+            self.y.rem_euclid(size) as i32,
+            self.z.rem_euclid(size) as i32,
+        )
+    }
+
+    /// The position as an offset, for positions that are the difference of two nearby ones
+    pub fn as_ivec3(&self) -> IVec3 {
+        IVec3::new(self.x as i32, self.y as i32, self.z as i32)
+    }
+
+    /// The position as f32, only precise for the difference of two nearby positions
+    pub fn as_vec3(&self) -> Vec3 {
+        Vec3::new(self.x as f32, self.y as f32, self.z as f32)
+    }
+
+    /// The position as f64, for sampling noise
+    pub fn as_f64_array(&self) -> [f64; 3] {
+        [self.x as f64, self.y as f64, self.z as f64]
+    }
+
+    /// The x and z of the position, the column it lies in
+    pub fn xz(&self) -> I64Vec2 {
+        I64Vec2::new(self.x, self.z)
+    }
+}
+
+impl From<IVec3> for I64Vec3 {
+    fn from(value: IVec3) -> Self {
+        Self::new(value.x as i64, value.y as i64, value.z as i64)
+    }
+}
+
+impl fmt::Display for I64Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}, {}]", self.x, self.y, self.z)
+    }
+}
+
+impl Add for I64Vec3 {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for I64Vec3 {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+/// Stepping by an offset
+impl Add<IVec3> for I64Vec3 {
+    type Output = Self;
+    fn add(self, offset: IVec3) -> Self {
+        self + I64Vec3::from(offset)
+    }
+}
+
+impl Sub<IVec3> for I64Vec3 {
+    type Output = Self;
+    fn sub(self, offset: IVec3) -> Self {
+        self - I64Vec3::from(offset)
+    }
+}
+
+impl Mul<i64> for I64Vec3 {
+    type Output = Self;
+    fn mul(self, scale: i64) -> Self {
+        Self::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+/// Column of the world, as the x and z of a world position, in voxels or in larger cells
+/// like the far terrain's tiles
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct I64Vec2 {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl I64Vec2 {
+    pub const fn new(x: i64, y: i64) -> Self {
+        Self { x, y }
+    }
+
+    /// Per axis, the cell of `size` the column lies in
+    pub fn div_euclid(self, size: i64) -> Self {
+        Self::new(self.x.div_euclid(size), self.y.div_euclid(size))
+    }
+
+    /// The column as an offset, for columns that are the difference of two nearby ones
+    pub fn as_ivec2(&self) -> IVec2 {
+        IVec2::new(self.x as i32, self.y as i32)
+    }
+}
+
+impl fmt::Display for I64Vec2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.x, self.y)
+    }
+}
+
+impl Sub for I64Vec2 {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+/// Stepping by an offset
+impl Add<IVec2> for I64Vec2 {
+    type Output = Self;
+    fn add(self, offset: IVec2) -> Self {
+        Self::new(self.x + offset.x as i64, self.y + offset.y as i64)
+    }
+}
+
+impl Sub<IVec2> for I64Vec2 {
+    type Output = Self;
+    fn sub(self, offset: IVec2) -> Self {
+        Self::new(self.x - offset.x as i64, self.y - offset.y as i64)
+    }
+}
+
+impl Mul<i64> for I64Vec2 {
+    type Output = Self;
+    fn mul(self, scale: i64) -> Self {
+        Self::new(self.x * scale, self.y * scale)
+    }
+}
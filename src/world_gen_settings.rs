@@ -0,0 +1,283 @@
+use std::{fmt, fs, str::FromStr};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{file_watcher::FileWatcher, noise_graph::NoiseNode, voxel::VoxelType};
+
+pub const WORLD_GEN_SETTINGS_PATH: &str = "assets/world_gen.ron";
+pub const DEFAULT_SEA_LEVEL: i32 = 0;
+
+/// Reads the world generation settings from `WORLD_GEN_SETTINGS_PATH`, and reloads them
+/// whenever the file is saved, so terrain can be tweaked while the game is running
+pub struct WorldGenSettingsPlugin;
+
+impl Plugin for WorldGenSettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WorldGenSettings::load_or_default(WORLD_GEN_SETTINGS_PATH))
+            .insert_resource(WorldGenSettingsWatcher(FileWatcher::new(
+                WORLD_GEN_SETTINGS_PATH,
+            )))
+            .add_system(watch_settings_file);
+    }
+}
+
+#[derive(Debug)]
+pub enum WorldGenSettingsError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+/// The generator `ChunkManager::load_chunks` fills new chunks with
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Generator {
+    #[default]
+    Perlin,
+    /// Terrain shaped by a noise graph instead of the octaves of Perlin noise, with the
+    /// same sea, lakes and beaches. Voxels where `density` is above `threshold` are solid
+    Noise { density: NoiseNode },
+    /// Flat world made of `layers`, stacked upwards from `base_height`
+    Flat {
+        layers: FlatLayers,
+        #[serde(default)]
+        base_height: i32,
+    },
+}
+
+/// Layers of a flat world from the bottom up, written like `"1*bedrock,3*dirt,1*grass"`.
+/// The count may be left out for layers one voxel thick
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FlatLayers {
+    layers: Vec<(u32, VoxelType)>,
+}
+
+#[derive(Debug)]
+pub struct LayerSpecError(String);
+
+impl fmt::Display for LayerSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid layer spec: {}", self.0)
+    }
+}
+
+impl FlatLayers {
+    /// Height above the bottom layer of the topmost layer that isn't air
+    pub fn surface_height(&self) -> Option<i32> {
+        let mut top = 0;
+        let mut surface = None;
+        for (count, voxel_type) in self.layers.iter() {
+            top += *count as i32;
+            if *count > 0 && *voxel_type != VoxelType::None {
+                surface = Some(top - 1);
+            }
+        }
+        surface
+    }
+
+    /// The voxel type of the layer at the given height above the bottom layer
+    pub fn get(&self, height: i32) -> Option<VoxelType> {
+        if height < 0 {
+            return None;
+        }
+        let mut top = 0;
+        for (count, voxel_type) in self.layers.iter() {
+            top += *count as i32;
+            if height < top {
+                return Some(*voxel_type);
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for FlatLayers {
+    type Err = LayerSpecError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut layers = Vec::new();
+        // The layers' heights are i32 like voxel positions, so all of them have to fit one
+        let mut height = 0i32;
+        for layer in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|layer| !layer.is_empty())
+        {
+            let (count, name) = match layer.split_once('*') {
+                Some((count, name)) => {
+                    let count = count
+                        .trim()
+                        .parse::<u32>()
+                        .map_err(|_| LayerSpecError(format!("bad layer count in \"{layer}\"")))?;
+                    (count, name.trim())
+                }
+                None => (1, layer),
+            };
+            let voxel_type = VoxelType::from_name(name)
+                .ok_or_else(|| LayerSpecError(format!("unknown block \"{name}\"")))?;
+            height = i32::try_from(count)
+                .ok()
+                .and_then(|count| height.checked_add(count))
+                .ok_or_else(|| LayerSpecError(format!("layers too thick at \"{layer}\"")))?;
+            layers.push((count, voxel_type));
+        }
+
+        if layers.is_empty() {
+            return Err(LayerSpecError(format!("no layers in \"{spec}\"")));
+        }
+        Ok(FlatLayers { layers })
+    }
+}
+
+impl TryFrom<String> for FlatLayers {
+    type Error = LayerSpecError;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        spec.parse()
+    }
+}
+
+impl From<FlatLayers> for String {
+    fn from(flat_layers: FlatLayers) -> Self {
+        flat_layers
+            .layers
+            .iter()
+            .map(|(count, voxel_type)| format!("{}*{}", count, voxel_type.name()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldGenSettings {
+    pub generator: Generator,
+    pub seed: u32,
+    /// Frequency of the terrain noise, smaller values give larger features
+    pub scale: f64,
+    /// Noise density above which a voxel is solid
+    pub threshold: f64,
+    /// Layers of noise added on top of each other, each at double the frequency and half the amplitude
+    pub octaves: u32,
+    /// Air at or below this height is filled with water, by the Perlin and Noise generators
+    pub sea_level: i32,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        Self {
+            generator: Generator::Perlin,
+            seed: 1337,
+            scale: 0.027,
+            threshold: 0.3,
+            octaves: 1,
+            sea_level: DEFAULT_SEA_LEVEL,
+        }
+    }
+}
+
+impl WorldGenSettings {
+    pub fn load(path: &str) -> Result<Self, WorldGenSettingsError> {
+        let contents = fs::read_to_string(path).map_err(WorldGenSettingsError::Io)?;
+        ron::from_str(&contents).map_err(WorldGenSettingsError::Parse)
+    }
+
+    pub fn load_or_default(path: &str) -> Self {
+        match WorldGenSettings::load(path) {
+            Ok(settings) => settings,
+            Err(error) => {
+                println!("Using default world gen settings, failed to load {path}: {error:?}");
+                WorldGenSettings::default()
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct WorldGenSettingsWatcher(pub FileWatcher);
+
+fn watch_settings_file(
+    time: Res<Time>,
+    mut watcher: ResMut<WorldGenSettingsWatcher>,
+    mut settings: ResMut<WorldGenSettings>,
+) {
+    if !watcher.0.changed(time.delta()) {
+        return;
+    }
+
+    let path = &watcher.0.path;
+    match WorldGenSettings::load(path) {
+        // Only touch the resource on an actual change, as that regenerates the world
+        Ok(new_settings) => {
+            if *settings != new_settings {
+                println!("Reloaded world gen settings from {path}");
+                *settings = new_settings;
+            }
+        }
+        Err(error) => println!("Failed to reload {path}: {error:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(spec: &str) -> String {
+        match spec.parse::<FlatLayers>() {
+            Ok(layers) => panic!("\"{spec}\" parsed as {layers:?}"),
+            Err(LayerSpecError(message)) => message,
+        }
+    }
+
+    #[test]
+    fn layer_spec_parses_counts_and_names() {
+        let layers: FlatLayers = " 1*bedrock, 3 * dirt ,grass,".parse().unwrap();
+        assert_eq!(
+            layers.layers,
+            vec![
+                (1, VoxelType::Bedrock),
+                (3, VoxelType::Dirt),
+                (1, VoxelType::Grass)
+            ]
+        );
+        assert_eq!(layers.surface_height(), Some(4));
+        assert_eq!(
+            String::from(layers.clone()).parse::<FlatLayers>().unwrap(),
+            layers
+        );
+    }
+
+    #[test]
+    fn layer_spec_rejects_bad_counts() {
+        assert_eq!(parse_error("x*stone"), "bad layer count in \"x*stone\"");
+        assert_eq!(parse_error("-1*stone"), "bad layer count in \"-1*stone\"");
+        assert_eq!(
+            parse_error("4294967296*stone"),
+            "bad layer count in \"4294967296*stone\""
+        );
+        assert_eq!(
+            parse_error("2147483647*stone,1*dirt"),
+            "layers too thick at \"1*dirt\""
+        );
+    }
+
+    #[test]
+    fn layer_spec_rejects_unknown_blocks() {
+        assert_eq!(parse_error("2*stone,3*cheese"), "unknown block \"cheese\"");
+        assert_eq!(parse_error("Stone"), "unknown block \"Stone\"");
+    }
+
+    #[test]
+    fn layer_spec_rejects_empty_specs() {
+        assert_eq!(parse_error(""), "no layers in \"\"");
+        assert_eq!(parse_error(" , ,"), "no layers in \" , ,\"");
+    }
+
+    #[test]
+    fn layer_spec_rejects_stray_stars() {
+        assert_eq!(parse_error("*stone"), "bad layer count in \"*stone\"");
+        assert_eq!(parse_error("*"), "bad layer count in \"*\"");
+        assert_eq!(parse_error("3*"), "unknown block \"\"");
+        assert_eq!(parse_error("2**stone"), "unknown block \"*stone\"");
+    }
+}
@@ -0,0 +1,456 @@
+use std::{cell::OnceCell, cmp::Reverse, collections::BinaryHeap, sync::Mutex};
+
+use bevy::{
+    prelude::{IVec2, Vec3},
+    utils::HashMap,
+};
+use noise::{NoiseFn, Perlin};
+
+use crate::{
+    noise_graph::DensityFn,
+    region_cache::RegionCache,
+    voxel::{BlockTint, Voxel, VoxelType, FULL_DENSITY},
+    world_coords::{I64Vec2, I64Vec3},
+    world_gen_settings::{Generator, WorldGenSettings},
+};
+
+/// How far above sea level the shore is covered in sand
+pub const BEACH_HEIGHT: i32 = 2;
+pub const LAKE_SCALE: f64 = 0.011;
+pub const LAKE_THRESHOLD: f64 = 0.25;
+/// Highest a lake surface gets above sea level
+pub const LAKE_LEVEL: i32 = 8;
+/// Deepest a lake gets below `LAKE_LEVEL`. Low ground deeper than that drains the lake
+pub const LAKE_MAX_DEPTH: i32 = 12;
+/// Most columns a lake basin may cover, low ground in the lake areas spreading further is
+/// left dry
+pub const LAKE_MAX_AREA: usize = 4096;
+/// Side of the square regions of columns the lake cache is kept in
+pub const LAKE_REGION_SIZE: i64 = 128;
+/// Most regions the lake cache keeps, the one used longest ago is dropped first
+pub const MAX_LAKE_REGIONS: usize = 32;
+const HORIZONTAL: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+/// Range around sea level searched for the terrain surface of a column
+pub const SURFACE_SEARCH_HEIGHT: i32 = 64;
+pub const SURFACE_SEARCH_DEPTH: i32 = 32;
+/// Distance of the noise density from the threshold at which a voxel is completely full or
+/// empty, so smooth meshing can place the surface between voxels where it crosses
+pub const DENSITY_RANGE: f64 = 0.25;
+pub const CLIMATE_SCALE: f64 = 0.004;
+/// Tints of grass and water in the driest and the wettest climate, as linear RGB multiplied
+/// with the texture. Their sprites are grey, so the tint is all of their colour
+pub const GRASS_TINTS: [Vec3; 2] = [Vec3::new(0.39, 0.76, 0.26), Vec3::new(0.28, 0.9, 0.33)];
+pub const WATER_TINTS: [Vec3; 2] = [Vec3::new(0.16, 0.49, 0.86), Vec3::new(0.17, 0.44, 0.96)];
+
+/// The top of a column of the world, as seen from above
+#[derive(Clone, Copy, Debug)]
+pub struct ColumnSurface {
+    /// Height and type of the topmost solid voxel, not counting fluids on top of it
+    pub ground_height: i32,
+    pub ground_type: VoxelType,
+    /// Height and type of the topmost voxel, fluids included
+    pub top_height: i32,
+    pub top_type: VoxelType,
+}
+
+/// Column in the range of heights lakes can fill
+#[derive(Clone, Copy, Debug)]
+enum LakeColumn {
+    /// Not part of a basin, its ground is higher than any lake or deeper than a lake gets
+    Outside,
+    /// Part of a basin that drains, lies outside the lake areas or is too large
+    Dry,
+    Water {
+        /// Height of the lake bed
+        ground: i32,
+        /// Height of the topmost water voxel, the same across the basin
+        level: i32,
+    },
+}
+
+/// Terrain density made from one Perlin noise per octave
+struct PerlinTerrain {
+    octaves: Vec<Perlin>,
+    scale: f64,
+}
+
+impl PerlinTerrain {
+    fn new(settings: &WorldGenSettings) -> Self {
+        Self {
+            octaves: (0..settings.octaves.max(1))
+                .map(|octave| Perlin::new(settings.seed.wrapping_add(octave)))
+                .collect(),
+            scale: settings.scale,
+        }
+    }
+}
+
+impl NoiseFn<f64, 3> for PerlinTerrain {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        let mut density = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.scale;
+        for perlin in self.octaves.iter() {
+            density += perlin.get(point.map(|value| value * frequency)) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        density / total_amplitude
+    }
+}
+
+/// The solid ground of the Perlin and Noise generators
+struct Terrain {
+    density: DensityFn,
+    threshold: f64,
+}
+
+impl Terrain {
+    fn new(settings: &WorldGenSettings) -> Self {
+        let density: DensityFn = match &settings.generator {
+            Generator::Noise { density } => density.build(settings.seed),
+            _ => Box::new(PerlinTerrain::new(settings)),
+        };
+        Self {
+            density,
+            threshold: settings.threshold,
+        }
+    }
+
+    fn density(&self, world_pos: &I64Vec3) -> f64 {
+        self.density.get(world_pos.as_f64_array())
+    }
+
+    fn solid(&self, world_pos: &I64Vec3) -> bool {
+        self.density(world_pos) > self.threshold
+    }
+
+    /// The noise density as a `Voxel::density`, with the threshold halfway to `FULL_DENSITY`
+    fn voxel_density(&self, density: f64) -> u8 {
+        let fraction = 0.5 + (density - self.threshold) / (DENSITY_RANGE * 2.0);
+        (fraction.clamp(0.0, 1.0) * FULL_DENSITY as f64).round() as u8
+    }
+}
+
+/// Generates the voxels of the world from `WorldGenSettings`.
+/// Every voxel only depends on its own position, so any part of the world can be asked for
+/// without generating the chunks around it
+pub struct WorldGenerator {
+    settings: WorldGenSettings,
+    terrain: Terrain,
+    lake_perlin: Perlin,
+    climate_perlin: Perlin,
+    /// Columns looked at for lakes lately. Basins are filled as a whole, the first time any
+    /// of their columns is asked for
+    lake_columns: Mutex<RegionCache<I64Vec2, LakeColumn>>,
+}
+
+/// One column of the world, generating its voxels with the column's lake looked up at most
+/// once for all of them
+pub struct GeneratorColumn<'a> {
+    generator: &'a WorldGenerator,
+    x: i64,
+    z: i64,
+    lake: OnceCell<LakeColumn>,
+}
+
+impl GeneratorColumn<'_> {
+    pub fn voxel(&self, y: i64) -> Voxel {
+        self.generator
+            .column_voxel(&I64Vec3::new(self.x, y, self.z), &self.lake)
+    }
+}
+
+impl WorldGenerator {
+    pub fn new(settings: &WorldGenSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            terrain: Terrain::new(settings),
+            lake_perlin: Perlin::new(settings.seed.wrapping_add(1)),
+            climate_perlin: Perlin::new(settings.seed.wrapping_sub(2)),
+            lake_columns: Mutex::new(RegionCache::new(LAKE_REGION_SIZE, MAX_LAKE_REGIONS)),
+        }
+    }
+
+    pub fn settings(&self) -> &WorldGenSettings {
+        &self.settings
+    }
+
+    pub fn voxel(&self, world_pos: &I64Vec3) -> Voxel {
+        self.column(world_pos.x, world_pos.z).voxel(world_pos.y)
+    }
+
+    /// The column, for generating many of its voxels
+    pub fn column(&self, x: i64, z: i64) -> GeneratorColumn<'_> {
+        GeneratorColumn {
+            generator: self,
+            x,
+            z,
+            lake: OnceCell::new(),
+        }
+    }
+
+    fn column_voxel(&self, world_pos: &I64Vec3, lake: &OnceCell<LakeColumn>) -> Voxel {
+        match &self.settings.generator {
+            Generator::Perlin | Generator::Noise { .. } => self.terrain_voxel(world_pos, lake),
+            Generator::Flat {
+                layers,
+                base_height,
+            } => match world_pos
+                .y
+                .checked_sub(*base_height as i64)
+                .and_then(|height| i32::try_from(height).ok())
+                .and_then(|height| layers.get(height))
+            {
+                Some(voxel_type) => Voxel::from_type(voxel_type),
+                None => Voxel::new_empty(),
+            },
+        }
+    }
+
+    /// Height of the topmost solid voxel in the column, for the Perlin and Noise generators only
+    /// looking within `SURFACE_SEARCH_HEIGHT` above and `SURFACE_SEARCH_DEPTH` below sea level
+    pub fn surface_height(&self, x: i64, z: i64) -> Option<i32> {
+        match &self.settings.generator {
+            Generator::Perlin | Generator::Noise { .. } => {
+                let sea_level = self.settings.sea_level;
+                let search_range =
+                    (sea_level - SURFACE_SEARCH_DEPTH)..=(sea_level + SURFACE_SEARCH_HEIGHT);
+                let mut above_solid = true;
+                for y in search_range.rev() {
+                    let solid = self.terrain.solid(&I64Vec3::new(x, y as i64, z));
+                    if solid && !above_solid {
+                        return Some(y);
+                    }
+                    above_solid = solid;
+                }
+                None
+            }
+            Generator::Flat {
+                layers,
+                base_height,
+            } => layers
+                .surface_height()
+                .and_then(|height| base_height.checked_add(height)),
+        }
+    }
+
+    /// Ground and top of the column, `None` where `surface_height` finds no ground and
+    /// there is no sea either
+    pub fn column_surface(&self, x: i64, z: i64) -> Option<ColumnSurface> {
+        let column = self.column(x, z);
+        let voxel_type = |y: i32| column.voxel(y as i64).voxel_type;
+        let sea_level = self.settings.sea_level;
+        // Without ground in reach of the search the sea may still cover the column, so put the
+        // ground at the bottom of the search
+        let ground_height = self.surface_height(x, z).or_else(|| {
+            voxel_type(sea_level)
+                .is_fluid()
+                .then_some(sea_level - SURFACE_SEARCH_DEPTH)
+        })?;
+
+        let mut top_height = ground_height;
+        while voxel_type(top_height + 1).is_fluid() {
+            top_height += 1;
+        }
+        Some(ColumnSurface {
+            ground_height,
+            ground_type: voxel_type(ground_height),
+            top_height,
+            top_type: voxel_type(top_height),
+        })
+    }
+
+    /// Colour of the tint in the column, blending from dry to wet with a slow climate noise.
+    /// Like the rest of the world it only depends on the position, so chunks meshed apart
+    /// still match along their borders
+    pub fn tint_color(&self, tint: BlockTint, x: i64, z: i64) -> Vec3 {
+        let climate = self
+            .climate_perlin
+            .get([x as f64 * CLIMATE_SCALE, z as f64 * CLIMATE_SCALE]);
+        let wetness = ((climate + 1.0) / 2.0).clamp(0.0, 1.0) as f32;
+        let [dry, wet] = match tint {
+            BlockTint::Grass => GRASS_TINTS,
+            BlockTint::Water => WATER_TINTS,
+        };
+        dry.lerp(wet, wetness)
+    }
+
+    fn terrain_voxel(&self, world_pos: &I64Vec3, lake: &OnceCell<LakeColumn>) -> Voxel {
+        let sea_level = self.settings.sea_level as i64;
+        let density = self.terrain.density(world_pos);
+        let voxel = if density > self.terrain.threshold {
+            // Terrain meeting the sea, or making up the bed of a lake, turns into sand
+            let above_pos = *world_pos + I64Vec3::Y;
+            let beach = !self.terrain.solid(&above_pos)
+                && (above_pos.y <= sea_level + BEACH_HEIGHT as i64
+                    || self.perlin_lake(&above_pos, lake));
+            if beach {
+                Voxel::from_type(VoxelType::Sand)
+            } else {
+                Voxel::from_type(VoxelType::Grass)
+            }
+        } else if world_pos.y <= sea_level || self.perlin_lake(world_pos, lake) {
+            Voxel::from_type(VoxelType::Water)
+        } else {
+            Voxel::new_empty()
+        };
+        // Kept for the water and air too, as smooth meshing puts the ground's surface in them
+        Voxel {
+            density: self.terrain.voxel_density(density),
+            ..voxel
+        }
+    }
+
+    /// Whether the air voxel at world_pos is part of a lake. Lakes fill the basins of low
+    /// ground whose lowest column lies in the areas picked by the lake noise, each to the
+    /// single level it would spill over at, and at most up to `LAKE_LEVEL`. `lake` is the
+    /// column's, looked up the first time it's needed
+    fn perlin_lake(&self, world_pos: &I64Vec3, lake: &OnceCell<LakeColumn>) -> bool {
+        let sea_level = self.settings.sea_level as i64;
+        if world_pos.y <= sea_level || world_pos.y > sea_level + LAKE_LEVEL as i64 {
+            return false;
+        }
+
+        match *lake.get_or_init(|| self.lake_column(world_pos.xz())) {
+            LakeColumn::Water { ground, level } => {
+                world_pos.y > ground as i64 && world_pos.y <= level as i64
+            }
+            _ => false,
+        }
+    }
+
+    fn in_lake_area(&self, column: I64Vec2) -> bool {
+        let lake_density = self
+            .lake_perlin
+            .get([column.x as f64 * LAKE_SCALE, column.y as f64 * LAKE_SCALE]);
+        lake_density >= LAKE_THRESHOLD
+    }
+
+    /// Height of the topmost solid voxel in the column, looking from the highest lake surface
+    /// down to `LAKE_MAX_DEPTH` below it
+    fn lake_ground(&self, column: I64Vec2) -> Option<i32> {
+        let lake_level = self.settings.sea_level + LAKE_LEVEL;
+        ((lake_level - LAKE_MAX_DEPTH)..=lake_level)
+            .rev()
+            .find(|y| {
+                self.terrain
+                    .solid(&I64Vec3::new(column.x, *y as i64, column.y))
+            })
+    }
+
+    fn lake_column(&self, column: I64Vec2) -> LakeColumn {
+        if let Some(lake) = self.lake_columns.lock().unwrap().get(column, &column) {
+            return lake;
+        }
+        // Found outside the lock, other threads may be looking for other basins. One that
+        // filled the same basin meanwhile found it the same, so its columns are kept
+        let basin = self.find_lake_basin(column);
+        let mut lake_columns = self.lake_columns.lock().unwrap();
+        let mut column_lake = LakeColumn::Outside;
+        for (basin_column, lake) in basin {
+            let kept = lake_columns.insert(basin_column, basin_column, lake);
+            if basin_column == column {
+                column_lake = kept;
+            }
+        }
+        column_lake
+    }
+
+    /// Fill the basin of low ground around `start`. The water level of every column is the
+    /// lowest height it would spill over at on the way out of the basin, found by flooding
+    /// in from there lowest first
+    fn find_lake_basin(&self, start: I64Vec2) -> HashMap<I64Vec2, LakeColumn> {
+        let lake_level = self.settings.sea_level + LAKE_LEVEL;
+        let mut grounds = HashMap::new();
+        let mut outlets = Vec::new();
+        let mut stack = vec![start];
+        match self.lake_ground(start) {
+            Some(ground) if ground < lake_level => grounds.insert(start, ground),
+            _ => return HashMap::from_iter([(start, LakeColumn::Outside)]),
+        };
+        let dry = |grounds: HashMap<I64Vec2, i32>| {
+            grounds
+                .into_keys()
+                .map(|column| (column, LakeColumn::Dry))
+                .collect()
+        };
+        while let Some(column) = stack.pop() {
+            if grounds.len() > LAKE_MAX_AREA {
+                return dry(grounds);
+            }
+            for offset in HORIZONTAL {
+                let neighbour = column + offset;
+                if grounds.contains_key(&neighbour) {
+                    continue;
+                }
+                // Reaching a dry column of a basin found before means this is the same basin,
+                // which is dry whichever of its columns it's filled from. Other columns may be
+                // what's left of a basin partly dropped from the cache, so it's filled again
+                let known = self.lake_columns.lock().unwrap().get(neighbour, &neighbour);
+                if matches!(known, Some(LakeColumn::Dry)) {
+                    return dry(grounds);
+                }
+                match self.lake_ground(neighbour) {
+                    // Ground higher than any lake holds the water in
+                    Some(ground) if ground >= lake_level => {}
+                    Some(ground) => {
+                        grounds.insert(neighbour, ground);
+                        stack.push(neighbour);
+                    }
+                    // A drop deeper than a lake gets, like into the sea, drains the basin
+                    None => outlets.push(column),
+                }
+            }
+        }
+
+        // Whether the basin holds a lake is up to its lowest point, wherever it's asked from
+        let lowest = grounds
+            .iter()
+            .min_by_key(|(column, ground)| (**ground, **column))
+            .map(|(column, _)| *column);
+        if !lowest.is_some_and(|column| self.in_lake_area(column)) {
+            return dry(grounds);
+        }
+
+        let mut levels: HashMap<I64Vec2, i32> =
+            grounds.keys().map(|column| (*column, lake_level)).collect();
+        let mut queue = BinaryHeap::new();
+        for column in outlets {
+            let level = grounds[&column];
+            if level < levels[&column] {
+                levels.insert(column, level);
+                queue.push(Reverse((level, column)));
+            }
+        }
+        while let Some(Reverse((level, column))) = queue.pop() {
+            if level > levels[&column] {
+                continue;
+            }
+            for offset in HORIZONTAL {
+                let neighbour = column + offset;
+                let Some(ground) = grounds.get(&neighbour) else { continue; };
+                let neighbour_level = level.max(*ground);
+                if neighbour_level < levels[&neighbour] {
+                    levels.insert(neighbour, neighbour_level);
+                    queue.push(Reverse((neighbour_level, neighbour)));
+                }
+            }
+        }
+
+        grounds
+            .into_iter()
+            .map(|(column, ground)| {
+                let level = levels[&column];
+                let lake = if level > ground {
+                    LakeColumn::Water { ground, level }
+                } else {
+                    LakeColumn::Dry
+                };
+                (column, lake)
+            })
+            .collect()
+    }
+}